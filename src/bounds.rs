@@ -0,0 +1,86 @@
+/// Axis-aligned box around a set of points
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Aabb {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+impl Aabb {
+    /// Box of the points, reduced to the origin when there's none
+    pub fn from_points<I: IntoIterator<Item = [f32; 3]>>(points: I) -> Self {
+        let mut points = points.into_iter();
+        let Some(first) = points.next() else {
+            return Aabb::default();
+        };
+        points.fold(Aabb { min: first, max: first }, |aabb, p| Aabb {
+            min: [0, 1, 2].map(|i| aabb.min[i].min(p[i])),
+            max: [0, 1, 2].map(|i| aabb.max[i].max(p[i])),
+        })
+    }
+
+    pub fn center(&self) -> [f32; 3] {
+        [0, 1, 2].map(|i| (self.min[i] + self.max[i]) / 2.0)
+    }
+
+    pub fn size(&self) -> [f32; 3] {
+        [0, 1, 2].map(|i| self.max[i] - self.min[i])
+    }
+
+    /// Where `p` is in the box, from 0 at `min` to 1 at `max`.
+    /// Flat axes put every point in the middle.
+    pub fn normalize(&self, p: [f32; 3]) -> [f32; 3] {
+        let size = self.size();
+        [0, 1, 2].map(|i| match size[i] > 0.0 {
+            true => (p[i] - self.min[i]) / size[i],
+            false => 0.5,
+        })
+    }
+}
+
+/// Sphere around a set of points, centered on their box so that the model turns in place
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct BoundingSphere {
+    pub center: [f32; 3],
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    pub fn from_points<I: IntoIterator<Item = [f32; 3]>>(aabb: &Aabb, points: I) -> Self {
+        let center = aabb.center();
+        let radius = points.into_iter()
+            .map(|p| crate::vector::length(crate::vector::sub(p, center)))
+            .fold(0.0, f32::max);
+        BoundingSphere { center, radius }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Aabb, BoundingSphere};
+
+    const POINTS: [[f32; 3]; 4] = [[1.0, -2.0, 0.0], [3.0, 2.0, 0.0], [-1.0, 0.0, 0.0], [2.0, 1.0, 0.0]];
+
+    #[test]
+    fn aabb() {
+        let aabb = Aabb::from_points(POINTS);
+        assert_eq!(aabb, Aabb { min: [-1.0, -2.0, 0.0], max: [3.0, 2.0, 0.0] });
+        assert_eq!(aabb.center(), [1.0, 0.0, 0.0]);
+        assert_eq!(aabb.size(), [4.0, 4.0, 0.0]);
+        // the flat axis puts every point in the middle
+        assert_eq!(aabb.normalize([0.0, 1.0, 7.0]), [0.25, 0.75, 0.5]);
+        assert_eq!(Aabb::from_points([]), Aabb::default());
+        let single = Aabb::from_points([[1.0, 2.0, 3.0]]);
+        assert_eq!((single.min, single.max), ([1.0, 2.0, 3.0], [1.0, 2.0, 3.0]));
+    }
+
+    #[test]
+    fn sphere() {
+        let aabb = Aabb::from_points(POINTS);
+        let sphere = BoundingSphere::from_points(&aabb, POINTS);
+        // centered on the box, reaching the furthest point and not its corners
+        assert_eq!(sphere.center, [1.0, 0.0, 0.0]);
+        assert_eq!(sphere.radius, 8.0f32.sqrt());
+        assert!(POINTS.iter().all(|p| crate::vector::length(crate::vector::sub(*p, sphere.center)) <= sphere.radius));
+        assert_eq!(BoundingSphere::from_points(&Aabb::default(), []), BoundingSphere::default());
+    }
+}
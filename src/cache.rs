@@ -0,0 +1,568 @@
+use std::{
+    env, fs,
+    io,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+use image::RgbaImage;
+use crate::{
+    bounds::{Aabb, BoundingSphere},
+    material::Material,
+    parsing::{Model, Normal, ParseOptions, Scalars, Submesh, Tangent, Vertex, Warning},
+    uv::Projection,
+};
+
+const MAGIC: &[u8; 8] = b"SCOPMESH";
+// bump it whenever the layout below or what the parser produces changes
+const VERSION: u32 = 10;
+
+fn absolute(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or(path.to_path_buf())
+}
+
+// size and modification time of a file, None if it can't be read
+fn stamp(path: &Path) -> Option<(u64, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some((metadata.len(), modified.as_nanos() as u64))
+}
+
+/// Where the caches are kept: `$XDG_CACHE_HOME/scop`, or `~/.cache/scop`
+pub fn directory() -> Option<PathBuf> {
+    let dir = match env::var_os("XDG_CACHE_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME")?).join(".cache"),
+    };
+    Some(dir.join("scop"))
+}
+
+// 64 bits FNV-1a, the names of the caches must not change with the toolchain
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+// where the cache of an obj file is kept in `dir`, under a name made from its absolute path
+fn cache_path(dir: &Path, obj: &Path) -> Option<PathBuf> {
+    let absolute = fs::canonicalize(obj).ok()?;
+    let stem = obj.file_stem()?.to_string_lossy();
+    Some(dir.join(format!("{stem}-{:016x}.mesh", fnv1a(absolute.as_os_str().as_encoded_bytes()))))
+}
+
+struct Encoder(Vec<u8>);
+
+impl Encoder {
+    fn u32(&mut self, value: u32) {
+        self.0.extend(value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.0.extend(value.to_le_bytes());
+    }
+
+    fn len(&mut self, value: usize) {
+        self.u64(value as u64);
+    }
+
+    fn f32s(&mut self, values: &[f32]) {
+        for value in values {
+            self.0.extend(value.to_le_bytes());
+        }
+    }
+
+    fn str(&mut self, value: &str) {
+        self.len(value.len());
+        self.0.extend(value.as_bytes());
+    }
+
+    fn option_str(&mut self, value: Option<&str>) {
+        match value {
+            Some(value) => {
+                self.0.push(1);
+                self.str(value);
+            },
+            None => self.0.push(0),
+        }
+    }
+
+    // relative paths wouldn't work from another directory
+    fn path(&mut self, value: &Path) {
+        self.str(&absolute(value).to_string_lossy());
+    }
+
+    fn option_path(&mut self, value: Option<&PathBuf>) {
+        match value {
+            Some(value) => {
+                self.0.push(1);
+                self.path(value);
+            },
+            None => self.0.push(0),
+        }
+    }
+
+    fn stamp(&mut self, value: Option<(u64, u64)>) {
+        let (size, modified) = value.unwrap_or((u64::MAX, u64::MAX));
+        self.u64(size);
+        self.u64(modified);
+    }
+}
+
+// every read fails with None past the end of the bytes
+struct Decoder<'a>(&'a [u8]);
+
+impl<'a> Decoder<'a> {
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        if len > self.0.len() {
+            return None;
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.bytes(4)?.try_into().ok()?))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.bytes(8)?.try_into().ok()?))
+    }
+
+    // a length, checked against what's left so a corrupted one can't allocate too much
+    fn len(&mut self, item_size: usize) -> Option<usize> {
+        let len = usize::try_from(self.u64()?).ok()?;
+        match len.checked_mul(item_size)? <= self.0.len() {
+            true => Some(len),
+            false => None,
+        }
+    }
+
+    fn f32s<const N: usize>(&mut self) -> Option<[f32; N]> {
+        let mut values = [0.0; N];
+        for value in values.iter_mut() {
+            *value = f32::from_le_bytes(self.bytes(4)?.try_into().ok()?);
+        }
+        Some(values)
+    }
+
+    fn str(&mut self) -> Option<String> {
+        let len = self.len(1)?;
+        String::from_utf8(self.bytes(len)?.to_vec()).ok()
+    }
+
+    fn option_str(&mut self) -> Option<Option<String>> {
+        match self.u8()? {
+            0 => Some(None),
+            1 => self.str().map(Some),
+            _ => None,
+        }
+    }
+
+    fn option_path(&mut self) -> Option<Option<PathBuf>> {
+        Some(self.option_str()?.map(PathBuf::from))
+    }
+
+    fn stamp(&mut self) -> Option<Option<(u64, u64)>> {
+        match (self.u64()?, self.u64()?) {
+            (u64::MAX, u64::MAX) => Some(None),
+            stamp => Some(Some(stamp)),
+        }
+    }
+
+    fn u32s(&mut self) -> Option<Vec<u32>> {
+        let len = self.len(4)?;
+        (0..len).map(|_| self.u32()).collect()
+    }
+}
+
+fn encode_u32s(encoder: &mut Encoder, values: &[u32]) {
+    encoder.len(values.len());
+    for &value in values {
+        encoder.u32(value);
+    }
+}
+
+fn projection_index(projection: Option<Projection>) -> u32 {
+    projection.map_or(u32::MAX, |projection| Projection::ALL.iter().position(|p| *p == projection).unwrap() as u32)
+}
+
+// the header says which files and options the model was made from
+fn encode_header(encoder: &mut Encoder, obj: &Path, options: &ParseOptions, dependencies: &[PathBuf]) {
+    encoder.0.extend(MAGIC);
+    encoder.u32(VERSION);
+    encoder.f32s(&[options.crease_angle]);
+    encoder.u32(projection_index(Some(options.uv)));
+    encoder.len(options.resolution);
+    encoder.path(obj);
+    encoder.stamp(stamp(obj));
+    encoder.len(dependencies.len());
+    for dependency in dependencies {
+        encoder.path(dependency);
+        encoder.stamp(stamp(dependency));
+    }
+}
+
+fn encode_model(encoder: &mut Encoder, model: &Model) {
+    encoder.len(model.vertices.len());
+    for vertex in model.vertices.iter() {
+        let (x, y, z) = vertex.position;
+        encoder.f32s(&[x, y, z]);
+        encoder.f32s(&vertex.tex_coords);
+        encoder.f32s(&vertex.color);
+    }
+    encoder.len(model.normals.len());
+    for normal in model.normals.iter() {
+        let (x, y, z) = normal.normal;
+        encoder.f32s(&[x, y, z]);
+    }
+    encoder.len(model.tangents.len());
+    for tangent in model.tangents.iter() {
+        encoder.f32s(&tangent.tangent);
+    }
+    encode_u32s(encoder, &model.indices);
+    encode_u32s(encoder, &model.lines);
+    encode_u32s(encoder, &model.points);
+    encoder.f32s(&model.bounds.min);
+    encoder.f32s(&model.bounds.max);
+    encoder.f32s(&model.sphere.center);
+    encoder.f32s(&[model.sphere.radius]);
+    encoder.0.push(model.has_colors as u8);
+    encoder.len(model.submeshes.len());
+    for submesh in model.submeshes.iter() {
+        encoder.option_str(submesh.object.as_deref());
+        encoder.option_str(submesh.group.as_deref());
+        encoder.option_str(submesh.material.as_deref());
+        for bound in [submesh.start, submesh.end, submesh.line_start, submesh.line_end, submesh.point_start, submesh.point_end] {
+            encoder.len(bound);
+        }
+        encoder.f32s(&submesh.bounds.min);
+        encoder.f32s(&submesh.bounds.max);
+    }
+    encoder.len(model.material_libs.len());
+    for lib in model.material_libs.iter() {
+        encoder.str(lib);
+    }
+    encoder.len(model.materials.len());
+    for material in model.materials.iter() {
+        encoder.str(&material.name);
+        encoder.f32s(&material.ambient);
+        encoder.f32s(&material.diffuse);
+        encoder.f32s(&material.specular);
+        encoder.f32s(&[material.shininess, material.opacity]);
+        encoder.u32(material.illum);
+        encoder.option_path(material.diffuse_map.as_ref());
+        encoder.option_path(material.specular_map.as_ref());
+        encoder.option_path(material.bump_map.as_ref());
+        encoder.option_path(material.opacity_map.as_ref());
+        match &material.diffuse_image {
+            Some(image) => {
+                encoder.0.push(1);
+                encoder.u32(image.width());
+                encoder.u32(image.height());
+                encoder.len(image.len());
+                encoder.0.extend(image.as_raw());
+            },
+            None => encoder.0.push(0),
+        }
+    }
+    encoder.len(model.scalars.len());
+    for scalars in model.scalars.iter() {
+        encoder.str(&scalars.name);
+        encoder.len(scalars.values.len());
+        encoder.f32s(&scalars.values);
+    }
+    encoder.u32(projection_index(model.uv));
+    encode_u32s(encoder, &model.seams);
+    encoder.len(model.unreferenced.len());
+    for position in model.unreferenced.iter() {
+        encoder.f32s(position);
+    }
+    encoder.len(model.warnings.len());
+    for warning in model.warnings.iter() {
+        encoder.u64(warning.line.map_or(u64::MAX, |line| line as u64));
+        encoder.str(&warning.message);
+    }
+}
+
+fn decode_model(decoder: &mut Decoder) -> Option<Model> {
+    let vertices = (0..decoder.len(36)?)
+        .map(|_| {
+            let [x, y, z] = decoder.f32s()?;
+            Some(Vertex { position: (x, y, z), tex_coords: decoder.f32s()?, color: decoder.f32s()? })
+        })
+        .collect::<Option<Vec<Vertex>>>()?;
+    let normals = (0..decoder.len(12)?)
+        .map(|_| decoder.f32s().map(|[x, y, z]| Normal { normal: (x, y, z) }))
+        .collect::<Option<Vec<Normal>>>()?;
+    let tangents = (0..decoder.len(16)?)
+        .map(|_| decoder.f32s().map(|tangent| Tangent { tangent }))
+        .collect::<Option<Vec<Tangent>>>()?;
+    let indices = decoder.u32s()?;
+    let lines = decoder.u32s()?;
+    let points = decoder.u32s()?;
+    let bounds = Aabb { min: decoder.f32s()?, max: decoder.f32s()? };
+    let center = decoder.f32s()?;
+    let [radius] = decoder.f32s()?;
+    let sphere = BoundingSphere { center, radius };
+    let has_colors = decoder.u8()? == 1;
+    let submeshes = (0..decoder.len(1)?)
+        .map(|_| Some(Submesh {
+            object: decoder.option_str()?,
+            group: decoder.option_str()?,
+            material: decoder.option_str()?,
+            start: usize::try_from(decoder.u64()?).ok()?,
+            end: usize::try_from(decoder.u64()?).ok()?,
+            line_start: usize::try_from(decoder.u64()?).ok()?,
+            line_end: usize::try_from(decoder.u64()?).ok()?,
+            point_start: usize::try_from(decoder.u64()?).ok()?,
+            point_end: usize::try_from(decoder.u64()?).ok()?,
+            bounds: Aabb { min: decoder.f32s()?, max: decoder.f32s()? },
+        }))
+        .collect::<Option<Vec<Submesh>>>()?;
+    let material_libs = (0..decoder.len(1)?).map(|_| decoder.str()).collect::<Option<Vec<String>>>()?;
+    let materials = (0..decoder.len(1)?)
+        .map(|_| {
+            let mut material = Material::new(&decoder.str()?);
+            material.ambient = decoder.f32s()?;
+            material.diffuse = decoder.f32s()?;
+            material.specular = decoder.f32s()?;
+            [material.shininess, material.opacity] = decoder.f32s()?;
+            material.illum = decoder.u32()?;
+            material.diffuse_map = decoder.option_path()?;
+            material.specular_map = decoder.option_path()?;
+            material.bump_map = decoder.option_path()?;
+            material.opacity_map = decoder.option_path()?;
+            material.diffuse_image = match decoder.u8()? {
+                0 => None,
+                1 => {
+                    let (width, height) = (decoder.u32()?, decoder.u32()?);
+                    let len = decoder.len(1)?;
+                    if len as u64 != width as u64 * height as u64 * 4 {
+                        return None;
+                    }
+                    Some(RgbaImage::from_raw(width, height, decoder.bytes(len)?.to_vec())?)
+                },
+                _ => return None,
+            };
+            Some(material)
+        })
+        .collect::<Option<Vec<Material>>>()?;
+    let scalars = (0..decoder.len(1)?)
+        .map(|_| {
+            let name = decoder.str()?;
+            let values = (0..decoder.len(4)?).map(|_| decoder.f32s().map(|[x]| x)).collect::<Option<Vec<f32>>>()?;
+            Some(Scalars { name, values })
+        })
+        .collect::<Option<Vec<Scalars>>>()?;
+    let uv = match decoder.u32()? {
+        u32::MAX => None,
+        i => Some(*Projection::ALL.get(i as usize)?),
+    };
+    let seams = decoder.u32s()?;
+    let unreferenced = (0..decoder.len(12)?).map(|_| decoder.f32s()).collect::<Option<Vec<[f32; 3]>>>()?;
+    let warnings = (0..decoder.len(1)?)
+        .map(|_| Some(Warning {
+            line: match decoder.u64()? {
+                u64::MAX => None,
+                line => Some(usize::try_from(line).ok()?),
+            },
+            message: decoder.str()?,
+        }))
+        .collect::<Option<Vec<Warning>>>()?;
+    // a model pointing outside of its vertices would crash the viewer
+    let in_range = |list: &[u32]| list.iter().all(|&i| (i as usize) < vertices.len());
+    let valid = normals.len() == vertices.len()
+        && tangents.len() == vertices.len()
+        && scalars.iter().all(|scalars| scalars.values.len() == vertices.len())
+        && in_range(&indices) && in_range(&lines) && in_range(&points) && in_range(&seams)
+        && seams.len() <= vertices.len()
+        && submeshes.iter().all(|submesh| {
+            submesh.start <= submesh.end && submesh.end <= indices.len()
+                && submesh.line_start <= submesh.line_end && submesh.line_end <= lines.len()
+                && submesh.point_start <= submesh.point_end && submesh.point_end <= points.len()
+        });
+    match valid && decoder.0.is_empty() {
+        true => Some(Model {
+            vertices, normals, tangents, indices, lines, points, bounds, sphere, has_colors,
+            submeshes, material_libs, materials, scalars, uv, seams, unreferenced, warnings,
+        }),
+        false => None,
+    }
+}
+
+/// The cached model of `obj` in `dir`, None if there's none or if it's stale: made by
+/// another version of scop, with other options, or from files changed since
+pub fn read(dir: &Path, obj: &Path, options: &ParseOptions) -> Option<Model> {
+    let bytes = fs::read(cache_path(dir, obj)?).ok()?;
+    let mut decoder = Decoder(&bytes);
+    if decoder.bytes(MAGIC.len())? != MAGIC || decoder.u32()? != VERSION {
+        return None;
+    }
+    let [crease_angle] = decoder.f32s()?;
+    let projection = decoder.u32()?;
+    let resolution = decoder.u64()?;
+    let path = decoder.str()?;
+    let same_options = crease_angle == options.crease_angle
+        && projection == projection_index(Some(options.uv))
+        && resolution == options.resolution as u64;
+    if !same_options || Path::new(&path) != absolute(obj) || decoder.stamp()? != stamp(obj) {
+        return None;
+    }
+    for _ in 0..decoder.len(17)? {
+        let dependency = decoder.str()?;
+        if decoder.stamp()? != stamp(Path::new(&dependency)) {
+            return None;
+        }
+    }
+    decode_model(&mut decoder)
+}
+
+/// Save the model of `obj` in `dir`, along with the state of the other files it was made from:
+/// material libraries, glTF buffers and images
+pub fn write(dir: &Path, obj: &Path, options: &ParseOptions, model: &Model, dependencies: &[PathBuf]) -> io::Result<()> {
+    let path = cache_path(dir, obj).ok_or(io::Error::new(io::ErrorKind::NotFound, "the file can't be found"))?;
+    let mut encoder = Encoder(Vec::new());
+    encode_header(&mut encoder, obj, options, dependencies);
+    encode_model(&mut encoder, model);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    // a crash while writing leaves the previous cache, not half of a new one
+    let partial = path.with_extension("partial");
+    fs::write(&partial, &encoder.0)?;
+    fs::rename(&partial, &path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::{Path, PathBuf}, time::{Duration, SystemTime}};
+    use super::{cache_path, fnv1a, read};
+    use crate::parsing::ParseOptions;
+
+    const OBJ: &str = "v 0 0 0\nv 1 0 0\nv 0 1 0\nv 1 1 0\nf 1 2 4 3\n";
+
+    // a model of its own for every test, cached in the `cache` directory next to it
+    fn test_file(name: &str, file: &str, content: &[u8]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("scop-cache-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(file), content).unwrap();
+        dir.join(file)
+    }
+
+    fn cache_dir(path: &Path) -> PathBuf {
+        path.with_file_name("cache")
+    }
+
+    fn load(path: &Path) {
+        crate::loader::load(path, &ParseOptions::default(), Some(&cache_dir(path))).unwrap();
+    }
+
+    fn touch(path: &Path) {
+        let later = SystemTime::now() + Duration::from_secs(60);
+        fs::File::options().write(true).open(path).unwrap().set_modified(later).unwrap();
+    }
+
+    fn cached(path: &Path) -> Option<String> {
+        read(&cache_dir(path), path, &ParseOptions::default()).map(|model| format!("{:?} {:?}", model.vertices, model.indices))
+    }
+
+    #[test]
+    fn hit() {
+        let path = test_file("hit", "quad.obj", OBJ.as_bytes());
+        let model = crate::loader::load(&path, &ParseOptions::default(), Some(&cache_dir(&path))).unwrap();
+        assert_eq!(cached(&path), Some(format!("{:?} {:?}", model.vertices, model.indices)));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn stable_names() {
+        // the reference values of FNV-1a, a name must not change from one build to the next
+        assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x85944171f73967e8);
+    }
+
+    #[test]
+    fn invalidation() {
+        let path = test_file("invalidation", "quad.obj", OBJ.as_bytes());
+        load(&path);
+        assert!(cached(&path).is_some());
+        for options in [
+            ParseOptions { crease_angle: 30.0, ..ParseOptions::default() },
+            ParseOptions { resolution: 3, ..ParseOptions::default() },
+        ] {
+            assert!(read(&cache_dir(&path), &path, &options).is_none());
+        }
+        // the same size, only the modification time changes
+        touch(&path);
+        assert!(cached(&path).is_none());
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn dependencies() {
+        let mtl = "newmtl red\nKd 1 0 0\n";
+        let path = test_file("mtl", "quad.obj", format!("mtllib colors.mtl\nusemtl red\n{OBJ}").as_bytes());
+        fs::write(path.with_file_name("colors.mtl"), mtl).unwrap();
+        load(&path);
+        assert!(cached(&path).is_some());
+        touch(&path.with_file_name("colors.mtl"));
+        assert!(cached(&path).is_none());
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+
+        // a triangle whose positions are in an external buffer
+        let positions: Vec<u8> = [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0].iter().flat_map(|x| x.to_le_bytes()).collect();
+        let gltf = r#"{
+            "asset": {"version": "2.0"},
+            "buffers": [{"uri": "positions.bin", "byteLength": 36}],
+            "bufferViews": [{"buffer": 0, "byteLength": 36}],
+            "accessors": [{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                "min": [0, 0, 0], "max": [1, 1, 0]}],
+            "meshes": [{"primitives": [{"attributes": {"POSITION": 0}}]}],
+            "nodes": [{"mesh": 0}]
+        }"#;
+        let path = test_file("gltf", "triangle.gltf", gltf.as_bytes());
+        fs::write(path.with_file_name("positions.bin"), &positions).unwrap();
+        load(&path);
+        assert!(cached(&path).is_some());
+        touch(&path.with_file_name("positions.bin"));
+        assert!(cached(&path).is_none());
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn corrupted() {
+        let path = test_file("corrupted", "quad.obj", OBJ.as_bytes());
+        load(&path);
+        let bytes = fs::read(cache_path(&cache_dir(&path), &path).unwrap()).unwrap();
+        // cut anywhere
+        for len in 0..bytes.len() {
+            fs::write(cache_path(&cache_dir(&path), &path).unwrap(), &bytes[..len]).unwrap();
+            assert!(cached(&path).is_none(), "truncated to {len} bytes");
+        }
+        // followed by garbage
+        fs::write(cache_path(&cache_dir(&path), &path).unwrap(), [&bytes[..], &[0]].concat()).unwrap();
+        assert!(cached(&path).is_none());
+        // made by another version
+        let mut wrong = bytes.clone();
+        wrong[8..12].copy_from_slice(&0u32.to_le_bytes());
+        fs::write(cache_path(&cache_dir(&path), &path).unwrap(), &wrong).unwrap();
+        assert!(cached(&path).is_none());
+        // any byte changed may give another model, but never a crash
+        for i in 0..bytes.len() {
+            let mut wrong = bytes.clone();
+            wrong[i] ^= 0xff;
+            fs::write(cache_path(&cache_dir(&path), &path).unwrap(), &wrong).unwrap();
+            if let Some(model) = read(&cache_dir(&path), &path, &ParseOptions::default()) {
+                assert!(model.indices.iter().all(|&i| (i as usize) < model.vertices.len()));
+            }
+        }
+        fs::write(cache_path(&cache_dir(&path), &path).unwrap(), &bytes).unwrap();
+        assert!(cached(&path).is_some());
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -9,6 +9,7 @@ pub fn match_event_keyboard(
     player: &mut [f32; 6],
     rotations: &mut (f32, usize, bool),
     is_textured: &mut bool,
+    is_colored: &mut bool,
     is_enlightened: &mut bool,
     center: &[f32; 3],
 ) {
@@ -34,8 +35,9 @@ pub fn match_event_keyboard(
         VirtualKeyCode::End => player[1] -= *speed,
         VirtualKeyCode::W => player[2] += *speed,
         VirtualKeyCode::S => player[2] -= *speed,
-        // disable/enable textures/light
+        // disable/enable textures/vertex colors/light
         VirtualKeyCode::T => *is_textured = !*is_textured,
+        VirtualKeyCode::V => *is_colored = !*is_colored,
         VirtualKeyCode::L => *is_enlightened = !*is_enlightened,
         // Center vision on object
         VirtualKeyCode::C => {
@@ -46,3 +48,27 @@ pub fn match_event_keyboard(
         _ => (),
     }
 }
+
+// 1 to 9 show/hide a group, 0 shows them all
+pub fn toggle_group(key: VirtualKeyCode, visible: &mut [bool], names: &[String]) {
+    let group = match key {
+        VirtualKeyCode::Key1 => 0,
+        VirtualKeyCode::Key2 => 1,
+        VirtualKeyCode::Key3 => 2,
+        VirtualKeyCode::Key4 => 3,
+        VirtualKeyCode::Key5 => 4,
+        VirtualKeyCode::Key6 => 5,
+        VirtualKeyCode::Key7 => 6,
+        VirtualKeyCode::Key8 => 7,
+        VirtualKeyCode::Key9 => 8,
+        _ => {
+            visible.iter_mut().for_each(|v| *v = true);
+            println!("showing all groups");
+            return
+        }
+    };
+    if let Some(v) = visible.get_mut(group) {
+        *v = !*v;
+        println!("{} {}", names[group], if *v { "shown" } else { "hidden" });
+    }
+}
@@ -0,0 +1,328 @@
+use crate::{
+    parsing::{Corner, ObjData, State, Warning},
+    vector::{cross, length, sub},
+};
+
+/// Basis of the free-form curves and surfaces, as named by `cstype`
+#[derive(Clone, Debug, PartialEq)]
+pub enum Basis {
+    Bezier,
+    BSpline,
+    /// bmatrix, cardinal and taylor, they aren't drawn
+    Other(String),
+}
+
+impl Basis {
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "bezier" => Basis::Bezier,
+            "bspline" => Basis::BSpline,
+            other => Basis::Other(other.to_string()),
+        }
+    }
+}
+
+/// A free-form statement. They are kept in file order and put together once the
+/// whole file is read, when every control point is known.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Statement {
+    /// `cstype [rat] <basis>`
+    Type { rational: bool, basis: Basis },
+    /// `deg <u> [v]`
+    Degree(usize, Option<usize>),
+    /// `curv u0 u1 v1 v2 ...`, the points are indices of positions
+    Curve { line: usize, range: [f32; 2], points: Vec<usize>, state: State },
+    /// `surf s0 s1 t0 t1 v1 v2 ...`, u varies first in the control points
+    Surface { line: usize, range: [[f32; 2]; 2], points: Vec<usize>, state: State },
+    /// `parm u|v p1 p2 ...`, 0 for u and 1 for v
+    Parameters { direction: usize, values: Vec<f32> },
+    /// `end`
+    End(usize),
+}
+
+// everything needed to evaluate along one direction
+struct Spline {
+    degree: usize,
+    knots: Vec<f32>,
+    // parameter range drawn
+    range: [f32; 2],
+}
+
+impl Spline {
+    // the knots of a bezier curve are its segment bounds, repeated to make a b-spline
+    fn new(basis: &Basis, degree: usize, parameters: &[f32], count: usize, range: [f32; 2]) -> Result<Self, String> {
+        if degree == 0 {
+            return Err(String::from("degree 0 can't be drawn"));
+        }
+        let knots = match basis {
+            Basis::Bezier => {
+                if count < degree + 1 || !(count - 1).is_multiple_of(degree) {
+                    return Err(format!("a bezier of degree {degree} can't have {count} control points"));
+                }
+                let segments = (count - 1) / degree;
+                let bounds: Vec<f32> = match parameters.len() {
+                    0 => (0..=segments).map(|i| i as f32).collect(),
+                    n if n == segments + 1 => parameters.to_vec(),
+                    n => return Err(format!("{segments} bezier segments need {} parameters, found {n}", segments + 1)),
+                };
+                let mut knots = vec![bounds[0]; degree + 1];
+                for &bound in bounds[1..segments].iter() {
+                    knots.extend(std::iter::repeat_n(bound, degree));
+                }
+                knots.extend(std::iter::repeat_n(bounds[segments], degree + 1));
+                knots
+            },
+            Basis::BSpline => match parameters.len() == count + degree + 1 {
+                true => parameters.to_vec(),
+                false => return Err(format!("{count} control points of degree {degree} need {} knots, found {}", count + degree + 1, parameters.len())),
+            },
+            Basis::Other(name) => return Err(format!("{name} curves and surfaces aren't supported")),
+        };
+        if knots.windows(2).any(|pair| pair[1] < pair[0]) {
+            return Err(String::from("the knots are not in increasing order"));
+        }
+        let (lo, hi) = (knots[degree], knots[count]);
+        let range = [range[0].clamp(lo, hi), range[1].clamp(lo, hi)];
+        match range[0] < range[1] {
+            true => Ok(Spline { degree, knots, range }),
+            false => Err(String::from("the parameter range is empty")),
+        }
+    }
+
+    // `resolution` steps in every knot span of the range
+    fn samples(&self, resolution: usize) -> Vec<f32> {
+        let [lo, hi] = self.range;
+        let mut bounds: Vec<f32> = self.knots.iter().copied().filter(|&k| k > lo && k < hi).collect();
+        bounds.insert(0, lo);
+        bounds.push(hi);
+        bounds.dedup();
+        let mut samples: Vec<f32> = bounds.windows(2)
+            .flat_map(|span| (0..resolution).map(move |i| span[0] + (span[1] - span[0]) * i as f32 / resolution as f32))
+            .collect();
+        samples.push(hi);
+        samples
+    }
+
+    // de Boor's algorithm on homogeneous points
+    fn evaluate(&self, points: &[[f32; 4]], t: f32) -> [f32; 4] {
+        let (p, knots) = (self.degree, &self.knots);
+        let k = (p..points.len()).rev().find(|&k| knots[k] <= t && knots[k] < knots[k + 1]).unwrap_or(p);
+        let mut d: Vec<[f32; 4]> = points[k - p..=k].to_vec();
+        for r in 1..=p {
+            for j in (r..=p).rev() {
+                let (from, to) = (knots[j + k - p], knots[j + 1 + k - r]);
+                let alpha = if to > from { (t - from) / (to - from) } else { 0.0 };
+                d[j] = [0, 1, 2, 3].map(|c| (1.0 - alpha) * d[j - 1][c] + alpha * d[j][c]);
+            }
+        }
+        d[p]
+    }
+}
+
+fn project([x, y, z, w]: [f32; 4]) -> [f32; 3] {
+    if w != 0.0 { [x / w, y / w, z / w] } else { [x, y, z] }
+}
+
+// control points in homogeneous coordinates, weighted by the `w` of their `v` when rational
+fn control_points(data: &ObjData, points: &[usize], rational: bool) -> Vec<[f32; 4]> {
+    points.iter()
+        .map(|&i| {
+            let [x, y, z] = data.positions[i];
+            let w = if rational { *data.weights.get(i).unwrap_or(&1.0) } else { 1.0 };
+            [x * w, y * w, z * w, w]
+        })
+        .collect()
+}
+
+/// Turn the free-form curves into polylines and the surfaces into triangles,
+/// with `resolution` segments in every knot span. Elements which can't be drawn are
+/// skipped with a warning.
+pub fn tessellate(data: &mut ObjData, resolution: usize) -> Vec<Warning> {
+    let resolution = resolution.max(1);
+    let mut warnings = Vec::new();
+    let (mut rational, mut basis, mut degree) = (false, Basis::Bezier, (1, None));
+    // the curve or surface waiting for its `end`
+    let mut element: Option<Statement> = None;
+    let mut parameters: [Vec<f32>; 2] = [Vec::new(), Vec::new()];
+    for statement in std::mem::take(&mut data.freeform) {
+        match statement {
+            Statement::Type { rational: r, basis: b } => (rational, basis) = (r, b),
+            Statement::Degree(u, v) => degree = (u, v),
+            statement@(Statement::Curve { .. } | Statement::Surface { .. }) => {
+                element = Some(statement);
+                parameters = [Vec::new(), Vec::new()];
+            },
+            Statement::Parameters { direction, values } => parameters[direction].extend(values),
+            Statement::End(line) => {
+                let drawn = match element.take() {
+                    Some(Statement::Curve { line, range, points, state }) => {
+                        curve(data, &basis, rational, degree.0, &parameters[0], range, &points, &state, resolution).map_err(|e| (line, e))
+                    },
+                    Some(Statement::Surface { line, range, points, state }) => {
+                        let degrees = (degree.0, degree.1.unwrap_or(degree.0));
+                        surface(data, line, &basis, rational, degrees, &parameters, range, &points, &state, resolution).map_err(|e| (line, e))
+                    },
+                    _ => Err((line, String::from("end without a curv or surf before"))),
+                };
+                if let Err((line, message)) = drawn {
+                    warnings.push(Warning { line: Some(line), message: format!("{message}, skipped") });
+                }
+            },
+        }
+    }
+    if let Some(Statement::Curve { line, .. } | Statement::Surface { line, .. }) = element {
+        warnings.push(Warning { line: Some(line), message: String::from("the file ends before the end of this element, skipped") });
+    }
+    warnings
+}
+
+#[allow(clippy::too_many_arguments)]
+fn curve(data: &mut ObjData, basis: &Basis, rational: bool, degree: usize, parameters: &[f32], range: [f32; 2], points: &[usize], state: &State, resolution: usize) -> Result<(), String> {
+    let spline = Spline::new(basis, degree, parameters, points.len(), range)?;
+    let control = control_points(data, points, rational);
+    let start = data.positions.len();
+    data.positions.extend(spline.samples(resolution).into_iter().map(|t| project(spline.evaluate(&control, t))));
+    let corners: Vec<Corner> = (start..data.positions.len())
+        .map(|position| Corner { position, texture: None, normal: None, color: None })
+        .collect();
+    data.add_polyline(&corners, state);
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn surface(data: &mut ObjData, line: usize, basis: &Basis, rational: bool, degrees: (usize, usize), parameters: &[Vec<f32>; 2], range: [[f32; 2]; 2], points: &[usize], state: &State, resolution: usize) -> Result<(), String> {
+    // the count of control points in u is given by its knots
+    let count_u = match basis {
+        Basis::BSpline => parameters[0].len().checked_sub(degrees.0 + 1).filter(|&n| n > 0).ok_or("not enough knots in u")?,
+        _ => match parameters[0].len() {
+            0 => degrees.0 + 1,
+            n => (n - 1) * degrees.0 + 1,
+        },
+    };
+    if !points.len().is_multiple_of(count_u) {
+        return Err(format!("{} control points can't make rows of {count_u}", points.len()));
+    }
+    let count_v = points.len() / count_u;
+    let u = Spline::new(basis, degrees.0, &parameters[0], count_u, range[0])?;
+    let v = Spline::new(basis, degrees.1, &parameters[1], count_v, range[1])?;
+    let control = control_points(data, points, rational);
+
+    let (samples_u, samples_v) = (u.samples(resolution), v.samples(resolution));
+    let start = data.positions.len();
+    for &t in samples_v.iter() {
+        // every row is evaluated at s, then the column they make at t
+        for &s in samples_u.iter() {
+            let column: Vec<[f32; 4]> = control.chunks(count_u).map(|row| u.evaluate(row, s)).collect();
+            data.positions.push(project(v.evaluate(&column, t)));
+        }
+    }
+    let width = samples_u.len();
+    let corner = |i: usize, j: usize| Corner { position: start + j * width + i, texture: None, normal: None, color: None };
+    for j in 0..samples_v.len() - 1 {
+        for i in 0..width - 1 {
+            for triangle in [[corner(i, j), corner(i + 1, j), corner(i + 1, j + 1)], [corner(i, j), corner(i + 1, j + 1), corner(i, j + 1)]] {
+                // collapsed edges, at the poles of a sphere
+                let [a, b, c] = triangle.map(|corner| data.positions[corner.position]);
+                if length(cross(sub(b, a), sub(c, a))) > 0.0 {
+                    data.add_polygon(line, &triangle, state);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parsing::{parsing, Model, ParseOptions};
+
+    fn model(obj: &str, resolution: usize) -> Model {
+        parsing(obj.as_bytes(), &ParseOptions { resolution, ..ParseOptions::default() }).unwrap()
+    }
+
+    fn position(model: &Model, i: u32) -> [f32; 3] {
+        let (x, y, z) = model.vertices[i as usize].position;
+        [x, y, z]
+    }
+
+    #[test]
+    fn quadratic_bezier() {
+        let control = [[0.0, 0.0, 0.0], [1.0, 2.0, 0.0], [3.0, 0.0, 1.0]];
+        let obj = "v 0 0 0\nv 1 2 0\nv 3 0 1\ncstype bezier\ndeg 2\ncurv 0 1 1 2 3\nend\n";
+        let model = model(obj, 4);
+        assert!(model.warnings.is_empty(), "{:?}", model.warnings);
+        // 4 segments, the polyline through the first corner of each then the last
+        assert_eq!(model.lines.len(), 8);
+        let mut samples: Vec<u32> = model.lines.iter().step_by(2).copied().collect();
+        samples.push(*model.lines.last().unwrap());
+        for (k, &i) in samples.iter().enumerate() {
+            let t = k as f32 / 4.0;
+            // (1 - t)² P0 + 2t(1 - t) P1 + t² P2
+            let weights = [(1.0 - t) * (1.0 - t), 2.0 * t * (1.0 - t), t * t];
+            let expected = [0, 1, 2].map(|c| (0..3).map(|j| weights[j] * control[j][c]).sum::<f32>());
+            let found = position(&model, i);
+            assert!((0..3).all(|c| (found[c] - expected[c]).abs() < 1e-5), "t = {t}: {found:?} instead of {expected:?}");
+        }
+    }
+
+    // Cox-de Boor recursion, the end of the last span belongs to it
+    fn basis(knots: &[f32], i: usize, p: usize, t: f32) -> f32 {
+        if p == 0 {
+            let last = knots[i + 1] == *knots.last().unwrap() && knots[i] < knots[i + 1];
+            return if knots[i] <= t && (t < knots[i + 1] || last && t == knots[i + 1]) { 1.0 } else { 0.0 };
+        }
+        let ratio = |a: f32, b: f32| if b > 0.0 { a / b } else { 0.0 };
+        ratio(t - knots[i], knots[i + p] - knots[i]) * basis(knots, i, p - 1, t)
+            + ratio(knots[i + p + 1] - t, knots[i + p + 1] - knots[i + 1]) * basis(knots, i + 1, p - 1, t)
+    }
+
+    #[test]
+    fn quadratic_bspline() {
+        let control = [[0.0, 0.0, 0.0], [1.0, 2.0, 0.0], [3.0, 2.0, 1.0], [4.0, 0.0, 0.0]];
+        let knots = [0.0, 0.0, 0.0, 1.0, 3.0, 3.0, 3.0];
+        let obj = "v 0 0 0\nv 1 2 0\nv 3 2 1\nv 4 0 0\ncstype bspline\ndeg 2\ncurv 0 3 1 2 3 4\nparm u 0 0 0 1 3 3 3\nend\n";
+        let model = model(obj, 2);
+        assert!(model.warnings.is_empty(), "{:?}", model.warnings);
+        // 2 segments in each of the spans [0, 1] and [1, 3]
+        assert_eq!(model.lines.len(), 8);
+        let mut samples: Vec<u32> = model.lines.iter().step_by(2).copied().collect();
+        samples.push(*model.lines.last().unwrap());
+        for (&t, &i) in [0.0, 0.5, 1.0, 2.0, 3.0].iter().zip(samples.iter()) {
+            let expected = [0, 1, 2].map(|c| (0..4).map(|j| basis(&knots, j, 2, t) * control[j][c]).sum::<f32>());
+            let found = position(&model, i);
+            assert!((0..3).all(|c| (found[c] - expected[c]).abs() < 1e-5), "t = {t}: {found:?} instead of {expected:?}");
+        }
+    }
+
+    #[test]
+    fn rational_quarter_circle() {
+        let obj = format!("v 1 0 0\nv 1 1 0 {}\nv 0 1 0\ncstype rat bezier\ndeg 2\ncurv 0 1 1 2 3\nend\n", std::f32::consts::FRAC_1_SQRT_2);
+        let model = model(&obj, 8);
+        assert!(model.warnings.is_empty(), "{:?}", model.warnings);
+        assert_eq!(model.lines.len(), 16);
+        // without the weight, the middle would be at (0.75, 0.75), off the circle
+        for &i in model.lines.iter() {
+            let [x, y, z] = position(&model, i);
+            assert!(((x * x + y * y).sqrt() - 1.0).abs() < 1e-5 && z == 0.0, "{:?}", [x, y, z]);
+        }
+        assert_eq!(position(&model, model.lines[0]), [1.0, 0.0, 0.0]);
+        let [x, y, _] = position(&model, *model.lines.last().unwrap());
+        assert!(x.abs() < 1e-6 && (y - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn bilinear_patch() {
+        let obj = "v 0 0 0\nv 2 0 0\nv 0 1 0\nv 2 1 1\ncstype bezier\ndeg 1 1\nsurf 0 1 0 1 1 2 3 4\nend\n";
+        for resolution in [1, 3, 5] {
+            let model = model(obj, resolution);
+            assert!(model.warnings.is_empty(), "{:?}", model.warnings);
+            // two triangles in every cell of the grid
+            assert_eq!(model.indices.len(), 3 * 2 * resolution * resolution, "resolution {resolution}");
+            // on the patch: z = u v with x = 2u and y = v
+            for &i in model.indices.iter() {
+                let [x, y, z] = position(&model, i);
+                assert!((z - x / 2.0 * y).abs() < 1e-5, "{:?}", [x, y, z]);
+            }
+        }
+    }
+}
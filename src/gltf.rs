@@ -0,0 +1,393 @@
+use std::{collections::HashSet, path::{Path, PathBuf}};
+use ::gltf::{buffer, image::{self, Format, Source}, material::AlphaMode, mesh::Mode, Gltf, Node};
+use ::image::RgbaImage;
+use crate::{
+    material::Material,
+    parsing::{build, Corner, Model, ObjData, ParseError, ParseOptions, State, Warning},
+};
+
+type Matrix = [[f32; 4]; 4];
+
+const IDENTITY: Matrix = [[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]];
+
+// glTF matrices are column major
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut m = [[0.0; 4]; 4];
+    for (c, column) in m.iter_mut().enumerate() {
+        for (r, value) in column.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[k][r] * b[c][k]).sum();
+        }
+    }
+    m
+}
+
+fn transform_point(m: &Matrix, p: [f32; 3]) -> [f32; 3] {
+    [0, 1, 2].map(|r| m[0][r] * p[0] + m[1][r] * p[1] + m[2][r] * p[2] + m[3][r])
+}
+
+// normals follow the inverse transpose, the cofactor matrix is the same up to a scale
+// and the normals are normalized anyway
+fn transform_normal(m: &Matrix, n: [f32; 3]) -> (f32, f32, f32) {
+    let column = |c: usize| [m[c][0], m[c][1], m[c][2]];
+    let (x, y, z) = (column(0), column(1), column(2));
+    let cofactor = [crate::vector::cross(y, z), crate::vector::cross(z, x), crate::vector::cross(x, y)];
+    let sign = crate::vector::dot(x, cofactor[0]).signum();
+    let n = [0, 1, 2].map(|r| sign * (cofactor[r][0] * n[0] + cofactor[r][1] * n[1] + cofactor[r][2] * n[2]));
+    let [x, y, z] = crate::vector::normalize(n);
+    (x, y, z)
+}
+
+// a mirroring transform turns the faces inside out
+fn is_mirrored(m: &Matrix) -> bool {
+    let column = |c: usize| [m[c][0], m[c][1], m[c][2]];
+    crate::vector::dot(column(0), crate::vector::cross(column(1), column(2))) < 0.0
+}
+
+// the file a uri points to, as the importer resolves it. None for the data embedded in it
+fn external(dir: &Path, uri: &str) -> Option<PathBuf> {
+    if let Some(path) = uri.strip_prefix("file://").or_else(|| uri.strip_prefix("file:")) {
+        return Some(PathBuf::from(path));
+    }
+    match uri.contains(':') {
+        true => None,
+        false => Some(dir.join(&*urlencoding::decode(uri).ok()?)),
+    }
+}
+
+// the decoded pixels as 8 bits rgba, whatever their channels and depth
+fn rgba(image: image::Data) -> Option<RgbaImage> {
+    let (channels, depth) = match image.format {
+        Format::R8 => (1, 1),
+        Format::R8G8 => (2, 1),
+        Format::R8G8B8 => (3, 1),
+        Format::R8G8B8A8 => (4, 1),
+        Format::R16 => (1, 2),
+        Format::R16G16 => (2, 2),
+        Format::R16G16B16 => (3, 2),
+        Format::R16G16B16A16 => (4, 2),
+        Format::R32G32B32FLOAT => (3, 4),
+        Format::R32G32B32A32FLOAT => (4, 4),
+    };
+    let value = |bytes: &[u8]| match bytes {
+        [byte] => *byte,
+        [a, b] => (u16::from_ne_bytes([*a, *b]) >> 8) as u8,
+        _ => (f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]).clamp(0.0, 1.0) * 255.0).round() as u8,
+    };
+    let pixels = image.pixels.chunks_exact(channels * depth)
+        .flat_map(|pixel| {
+            let c: Vec<u8> = pixel.chunks_exact(depth).map(value).collect();
+            match channels {
+                // grey, then grey and alpha
+                1 => [c[0], c[0], c[0], 255],
+                2 => [c[0], c[0], c[0], c[1]],
+                3 => [c[0], c[1], c[2], 255],
+                _ => [c[0], c[1], c[2], c[3]],
+            }
+        })
+        .collect();
+    RgbaImage::from_raw(image.width, image.height, pixels)
+}
+
+fn material_name(material: &::gltf::Material) -> Option<String> {
+    material.index().map(|i| material.name().map_or(format!("material{i}"), str::to_string))
+}
+
+/// Parse a glTF 2.0 file, `.gltf` with external or base64 embedded buffers, or `.glb`.
+/// The node hierarchy is flattened: every mesh is moved to world space and each of
+/// its primitives is drawn with a material made from its base color and texture.
+/// External files are looked for in `dir`, their paths are returned with the model.
+pub fn parsing_gltf(bytes: &[u8], dir: &Path, options: &ParseOptions) -> Result<(Model, Vec<PathBuf>), ParseError> {
+    let Gltf { document, blob } = Gltf::from_slice(bytes).map_err(|e| ParseError::Invalid(e.to_string()))?;
+    let buffer_paths = document.buffers().filter_map(|buffer| match buffer.source() {
+        buffer::Source::Uri(uri) => external(dir, uri),
+        buffer::Source::Bin => None,
+    });
+    let image_paths = document.images().filter_map(|image| match image.source() {
+        Source::Uri { uri, .. } => external(dir, uri),
+        Source::View { .. } => None,
+    });
+    let dependencies = buffer_paths.chain(image_paths).collect();
+    let buffers = ::gltf::import_buffers(&document, Some(dir), blob).map_err(|e| ParseError::Invalid(e.to_string()))?;
+    let mut warnings = Vec::new();
+
+    let materials: Vec<Material> = document.materials()
+        .filter_map(|material| {
+            let mut converted = Material::new(&material_name(&material)?);
+            let pbr = material.pbr_metallic_roughness();
+            let [r, g, b, a] = pbr.base_color_factor();
+            converted.diffuse = [r, g, b];
+            converted.ambient = [r * 0.2, g * 0.2, b * 0.2];
+            converted.opacity = if material.alpha_mode() == AlphaMode::Opaque { 1.0 } else { a };
+            let Some(source) = pbr.base_color_texture().map(|info| info.texture().source().source()) else {
+                return Some(converted);
+            };
+            // files are loaded by the viewer, embedded images are decoded now as they are gone once the file is parsed
+            if let Source::Uri { uri, .. } = source {
+                converted.diffuse_map = external(dir, uri);
+                if converted.diffuse_map.is_some() {
+                    return Some(converted);
+                }
+            }
+            // the importer doesn't check the view against its buffer
+            let decoded = match &source {
+                Source::View { view, .. } if buffers.get(view.buffer().index()).is_none_or(|buffer| view.offset() + view.length() > buffer.len()) => {
+                    Err(String::from("the buffer view is out of its buffer"))
+                },
+                _ => image::Data::from_source(source, Some(dir), &buffers).map_err(|e| e.to_string())
+                    .and_then(|image| rgba(image).ok_or_else(|| String::from("its pixels don't match its size"))),
+            };
+            match decoded {
+                Ok(image) => converted.diffuse_image = Some(image),
+                Err(e) => warnings.push(Warning {
+                    line: None,
+                    message: format!("the base color texture of {:?} can't be read: {e}", converted.name),
+                }),
+            }
+            Some(converted)
+        })
+        .collect();
+
+    // nodes of the default scene, or the roots of the hierarchy when there's no scene
+    let roots: Vec<Node> = match document.default_scene().or_else(|| document.scenes().next()) {
+        Some(scene) => scene.nodes().collect(),
+        None => {
+            let children: HashSet<usize> = document.nodes().flat_map(|node| node.children().map(|child| child.index())).collect();
+            document.nodes().filter(|node| !children.contains(&node.index())).collect()
+        },
+    };
+    let mut stack: Vec<(Node, Matrix)> = roots.into_iter().rev().map(|node| (node, IDENTITY)).collect();
+    let mut data = ObjData::default();
+    while let Some((node, parent)) = stack.pop() {
+        let world = multiply(&parent, &node.transform().matrix());
+        stack.extend(node.children().collect::<Vec<Node>>().into_iter().rev().map(|child| (child, world)));
+        let Some(mesh) = node.mesh() else {
+            continue
+        };
+        let object = node.name().or(mesh.name()).map(str::to_string);
+        for primitive in mesh.primitives() {
+            let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|data| &data.0[..]));
+            let Some(positions) = reader.read_positions() else {
+                continue
+            };
+            let base = [data.positions.len(), data.textures.len(), data.normals.len()];
+            data.positions.extend(positions.map(|p| transform_point(&world, p)));
+            let count = data.positions.len() - base[0];
+            let has_normals = match reader.read_normals() {
+                Some(normals) => {
+                    data.normals.extend(normals.take(count).map(|n| transform_normal(&world, n)));
+                    data.normals.len() - base[2] == count
+                },
+                None => false,
+            };
+            // the set of the base color texture, glTF textures start at the top
+            let set = primitive.material().pbr_metallic_roughness().base_color_texture().map_or(0, |info| info.tex_coord());
+            let has_textures = match reader.read_tex_coords(set) {
+                Some(textures) => {
+                    data.textures.extend(textures.into_f32().take(count).map(|[u, v]| [u, 1.0 - v]));
+                    data.textures.len() - base[1] == count
+                },
+                None => false,
+            };
+            if let Some(colors) = reader.read_colors(0) {
+                data.colors.resize(base[0], [1.0; 4]);
+                data.colors.extend(colors.into_rgba_f32().take(count));
+            }
+            let corner = |i: u32| -> Result<Corner, ParseError> {
+                let i = i as usize;
+                if i >= count {
+                    return Err(ParseError::Invalid(format!("mesh {:?}: index {i} is out of range, only {count} vertices are defined", mesh.name().unwrap_or(""))));
+                }
+                Ok(Corner {
+                    position: base[0] + i,
+                    texture: has_textures.then_some(base[1] + i),
+                    normal: has_normals.then_some(base[2] + i),
+                    color: None,
+                })
+            };
+            let indices: Vec<u32> = match reader.read_indices() {
+                Some(indices) => indices.into_u32().collect(),
+                None => (0..count as u32).collect(),
+            };
+            let state = State {
+                object: object.clone(),
+                material: material_name(&primitive.material()),
+                ..State::default()
+            };
+            let triangles: Vec<[u32; 3]> = match primitive.mode() {
+                Mode::Triangles => indices.chunks_exact(3).map(|t| [t[0], t[1], t[2]]).collect(),
+                // every other strip triangle is wound the other way
+                Mode::TriangleStrip => indices.windows(3)
+                    .enumerate()
+                    .map(|(i, t)| if i % 2 == 0 { [t[0], t[1], t[2]] } else { [t[1], t[0], t[2]] })
+                    .collect(),
+                Mode::TriangleFan => indices.windows(2).skip(1).map(|t| [indices[0], t[0], t[1]]).collect(),
+                Mode::Points => {
+                    for &i in indices.iter() {
+                        data.add_point(corner(i)?, &state);
+                    }
+                    continue
+                },
+                Mode::Lines | Mode::LineStrip | Mode::LineLoop => {
+                    let corners = indices.iter().map(|&i| corner(i)).collect::<Result<Vec<Corner>, ParseError>>()?;
+                    match primitive.mode() {
+                        Mode::Lines => corners.chunks_exact(2).for_each(|segment| data.add_polyline(segment, &state)),
+                        Mode::LineLoop if !corners.is_empty() => data.add_polyline(&[&corners[..], &corners[..1]].concat(), &state),
+                        _ => data.add_polyline(&corners, &state),
+                    }
+                    continue
+                },
+            };
+            let mirrored = is_mirrored(&world);
+            for (i, triangle) in triangles.into_iter().enumerate() {
+                let triangle = if mirrored { [triangle[0], triangle[2], triangle[1]] } else { triangle };
+                let corners = [corner(triangle[0])?, corner(triangle[1])?, corner(triangle[2])?];
+                // triangles are numbered in place of lines
+                data.add_polygon(i + 1, &corners, &state);
+            }
+        }
+    }
+    let mut model = build(data, options);
+    model.materials = materials;
+    model.warnings.extend(warnings);
+    Ok((model, dependencies))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Cursor, path::Path};
+    use super::parsing_gltf;
+    use crate::parsing::ParseOptions;
+
+    fn base64(bytes: &[u8]) -> String {
+        const DIGITS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        bytes.chunks(3)
+            .flat_map(|chunk| {
+                let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, &b)| bits | (b as u32) << (16 - 8 * i));
+                (0..4).map(move |i| match i <= chunk.len() {
+                    true => DIGITS[(bits >> (18 - 6 * i) & 63) as usize] as char,
+                    false => '=',
+                })
+            })
+            .collect()
+    }
+
+    // a red and a green pixel
+    fn png() -> Vec<u8> {
+        let image = image::RgbaImage::from_raw(2, 1, vec![255, 0, 0, 255, 0, 255, 0, 255]).unwrap();
+        let mut png = Cursor::new(Vec::new());
+        image.write_to(&mut png, image::ImageFormat::Png).unwrap();
+        png.into_inner()
+    }
+
+    // the positions of a triangle then its indices, as a line loop
+    fn geometry() -> Vec<u8> {
+        let mut buffer: Vec<u8> = [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0].iter().flat_map(|x| x.to_le_bytes()).collect();
+        buffer.extend([0u16, 1, 2, 0].iter().flat_map(|i| i.to_le_bytes()));
+        buffer
+    }
+
+    // a triangle drawn filled and as a line loop, textured with `image`
+    fn gltf(buffer: &str, image: &str, extra_views: &str) -> String {
+        format!(r#"{{
+            "asset": {{"version": "2.0"}},
+            "buffers": [{buffer}],
+            "bufferViews": [{{"buffer": 0, "byteLength": 36}}, {{"buffer": 0, "byteOffset": 36, "byteLength": 6}}{extra_views}],
+            "accessors": [
+                {{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 0]}},
+                {{"bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR"}}
+            ],
+            "images": [{image}],
+            "textures": [{{"source": 0}}],
+            "materials": [{{"name": "pixels", "pbrMetallicRoughness": {{"baseColorTexture": {{"index": 0}}}}}}],
+            "meshes": [{{"primitives": [
+                {{"attributes": {{"POSITION": 0}}, "indices": 1, "material": 0}},
+                {{"attributes": {{"POSITION": 0}}, "indices": 1, "mode": 2}}
+            ]}}],
+            "nodes": [{{"mesh": 0}}]
+        }}"#)
+    }
+
+    #[test]
+    fn data_uris() {
+        let buffer = format!(r#"{{"byteLength": 42, "uri": "data:application/octet-stream;base64,{}"}}"#, base64(&geometry()));
+        let image = format!(r#"{{"uri": "data:image/png;base64,{}"}}"#, base64(&png()));
+        let (model, dependencies) = parsing_gltf(gltf(&buffer, &image, "").as_bytes(), Path::new(""), &ParseOptions::default()).unwrap();
+        assert!(dependencies.is_empty());
+        assert!(model.warnings.is_empty(), "{:?}", model.warnings);
+        let image = model.materials[0].diffuse_image.as_ref().unwrap();
+        assert_eq!(image.dimensions(), (2, 1));
+        assert_eq!(image.get_pixel(1, 0).0, [0, 255, 0, 255]);
+        assert_eq!(model.materials[0].diffuse_map, None);
+        assert_eq!(model.indices.len(), 3);
+        // the loop is closed
+        assert_eq!(model.lines.len(), 6);
+        assert_eq!(model.lines[5], model.lines[0]);
+    }
+
+    #[test]
+    fn glb() {
+        // the geometry, then the image, aligned on 4 bytes
+        let mut bin = geometry();
+        bin.resize(44, 0);
+        let png = png();
+        bin.extend(&png);
+        bin.resize(bin.len().next_multiple_of(4), 0);
+        // the image in a view of `length` bytes
+        let glb = |length: usize| {
+            let view = format!(r#", {{"buffer": 0, "byteOffset": 44, "byteLength": {length}}}"#);
+            let image = r#"{"bufferView": 2, "mimeType": "image/png"}"#;
+            let mut json = gltf(&format!(r#"{{"byteLength": {}}}"#, bin.len()), image, &view).into_bytes();
+            json.resize(json.len().next_multiple_of(4), b' ');
+            let mut glb = b"glTF".to_vec();
+            glb.extend(2u32.to_le_bytes());
+            glb.extend((12 + 8 + json.len() as u32 + 8 + bin.len() as u32).to_le_bytes());
+            for (kind, chunk) in [(b"JSON", &json), (b"BIN\0", &bin)] {
+                glb.extend((chunk.len() as u32).to_le_bytes());
+                glb.extend(kind);
+                glb.extend(chunk);
+            }
+            parsing_gltf(&glb, Path::new(""), &ParseOptions::default()).unwrap().0
+        };
+        let model = glb(png.len());
+        assert!(model.warnings.is_empty(), "{:?}", model.warnings);
+        assert_eq!(model.materials[0].diffuse_image.as_ref().unwrap().get_pixel(0, 0).0, [255, 0, 0, 255]);
+        // an image past the end of the buffer is only a warning
+        let model = glb(png.len() + 1000);
+        assert!(model.warnings[0].message.ends_with("the buffer view is out of its buffer"), "{:?}", model.warnings);
+        assert_eq!(model.indices.len(), 3);
+    }
+
+    #[test]
+    fn external_files() {
+        let buffer = format!(r#"{{"byteLength": 42, "uri": "data:application/octet-stream;base64,{}"}}"#, base64(&geometry()));
+        let (model, dependencies) = parsing_gltf(gltf(&buffer, r#"{"uri": "my%20texture.png"}"#, "").as_bytes(), Path::new("models"), &ParseOptions::default()).unwrap();
+        assert_eq!(model.materials[0].diffuse_map.as_deref(), Some(Path::new("models/my texture.png")));
+        assert_eq!(dependencies, [Path::new("models/my texture.png")]);
+    }
+
+    #[test]
+    fn texture_coordinate_sets() {
+        // the positions of a triangle, then two sets of texture coordinates
+        let mut buffer: Vec<u8> = [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0].iter().flat_map(|x| x.to_le_bytes()).collect();
+        buffer.extend([0.0f32, 0.0, 1.0, 0.0, 0.0, 1.0, 0.5, 0.5, 0.5, 0.5, 0.5, 0.5].iter().flat_map(|x| x.to_le_bytes()));
+        let gltf = format!(r#"{{
+            "asset": {{"version": "2.0"}},
+            "buffers": [{{"byteLength": 84, "uri": "data:application/octet-stream;base64,{}"}}],
+            "bufferViews": [{{"buffer": 0, "byteLength": 36}}, {{"buffer": 0, "byteOffset": 36, "byteLength": 48}}],
+            "accessors": [
+                {{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 0]}},
+                {{"bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC2"}},
+                {{"bufferView": 1, "byteOffset": 24, "componentType": 5126, "count": 3, "type": "VEC2"}}
+            ],
+            "images": [{{"uri": "texture.png"}}],
+            "textures": [{{"source": 0}}],
+            "materials": [{{"pbrMetallicRoughness": {{"baseColorTexture": {{"index": 0, "texCoord": 1}}}}}}],
+            "meshes": [{{"primitives": [{{"attributes": {{"POSITION": 0, "TEXCOORD_0": 1, "TEXCOORD_1": 2}}, "material": 0}}]}}],
+            "nodes": [{{"mesh": 0}}]
+        }}"#, base64(&buffer));
+        let (model, _) = parsing_gltf(gltf.as_bytes(), Path::new(""), &ParseOptions::default()).unwrap();
+        assert!(model.warnings.is_empty(), "{:?}", model.warnings);
+        // the second set, flipped
+        assert!(model.vertices.iter().all(|vertex| vertex.tex_coords == [0.5, 0.5]), "{:?}", model.vertices);
+    }
+}
@@ -0,0 +1,208 @@
+use std::{collections::HashSet, fmt, time::Duration};
+use crate::{
+    bounds::Aabb,
+    parsing::Model,
+    validate::validate,
+    vector::{cross, dot, length, sub},
+};
+
+/// What's in a model, as printed by `scop info`
+pub struct Info {
+    pub path: String,
+    pub vertices: usize,
+    /// distinct positions, normals and texture coordinates among the vertices
+    pub positions: usize,
+    pub normals: usize,
+    pub texture_coordinates: usize,
+    /// name of the projection they were made with, None when they come from the file
+    pub generated_uvs: Option<&'static str>,
+    pub triangles: usize,
+    pub segments: usize,
+    pub points: usize,
+    /// none without vertices
+    pub bounds: Option<Aabb>,
+    pub area: f32,
+    /// only closed and consistently wound meshes have one
+    pub volume: Option<f32>,
+    pub groups: Vec<String>,
+    /// used by the faces, defined or not
+    pub materials: Vec<String>,
+    pub warnings: Vec<String>,
+    pub parse_time: Duration,
+}
+
+// -0.0 and 0.0 are the same coordinate but not the same bits
+fn distinct<const N: usize>(values: impl Iterator<Item = [f32; N]>) -> usize {
+    values.map(|value| value.map(|x| (x + 0.0).to_bits())).collect::<HashSet<[u32; N]>>().len()
+}
+
+impl Info {
+    pub fn new(path: &str, model: &Model, parse_time: Duration) -> Self {
+        let position = |i: u32| {
+            let (x, y, z) = model.vertices[i as usize].position;
+            [x, y, z]
+        };
+        let triangles: Vec<[[f32; 3]; 3]> = model.indices.chunks_exact(3)
+            .map(|t| [position(t[0]), position(t[1]), position(t[2])])
+            .collect();
+        // a sum of no floats is -0
+        let area = triangles.iter().fold(0.0, |area, [a, b, c]| area + length(cross(sub(*b, *a), sub(*c, *a))) / 2.0);
+        // signed volumes of the tetrahedra between the origin and every face
+        let report = validate(model);
+        // a flipped face would take its volume away instead of adding it
+        let closed = !triangles.is_empty()
+            && report.boundary_edges.is_empty()
+            && report.non_manifold_edges.is_empty()
+            && report.inconsistent_edges.is_empty();
+        let volume = closed.then(|| triangles.iter().map(|[a, b, c]| dot(*a, cross(*b, *c))).sum::<f32>().abs() / 6.0);
+        let mut materials: Vec<String> = Vec::new();
+        for material in model.submeshes.iter().filter_map(|submesh| submesh.material.as_ref()) {
+            if !materials.contains(material) {
+                materials.push(material.clone());
+            }
+        }
+        Info {
+            path: path.to_string(),
+            vertices: model.vertices.len(),
+            positions: distinct(model.vertices.iter().map(|v| [v.position.0, v.position.1, v.position.2])),
+            normals: distinct(model.normals.iter().map(|n| [n.normal.0, n.normal.1, n.normal.2])),
+            texture_coordinates: distinct(model.vertices.iter().map(|v| v.tex_coords)),
+            generated_uvs: model.uv.map(|projection| projection.name()),
+            triangles: triangles.len(),
+            segments: model.lines.len() / 2,
+            points: model.points.len(),
+            bounds: (!model.vertices.is_empty()).then_some(model.bounds),
+            area,
+            volume,
+            groups: model.group_names(),
+            materials,
+            warnings: model.warnings.iter().map(|warning| warning.to_string()).collect(),
+            parse_time,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let list = |values: &[String]| format!("[{}]", values.iter().map(|value| json_string(value)).collect::<Vec<String>>().join(", "));
+        let floats = |values: [f32; 3]| format!("[{}, {}, {}]", values[0], values[1], values[2]);
+        let fields = [
+            ("path", json_string(&self.path)),
+            ("vertices", self.vertices.to_string()),
+            ("positions", self.positions.to_string()),
+            ("normals", self.normals.to_string()),
+            ("texture_coordinates", self.texture_coordinates.to_string()),
+            ("generated_uvs", self.generated_uvs.map_or(String::from("null"), json_string)),
+            ("triangles", self.triangles.to_string()),
+            ("segments", self.segments.to_string()),
+            ("points", self.points.to_string()),
+            ("bounds", self.bounds.map_or(String::from("null"), |bounds| format!("{{\"min\": {}, \"max\": {}}}", floats(bounds.min), floats(bounds.max)))),
+            ("center", self.bounds.map_or(String::from("null"), |bounds| floats(bounds.center()))),
+            ("area", self.area.to_string()),
+            ("volume", self.volume.map_or(String::from("null"), |volume| volume.to_string())),
+            ("groups", list(&self.groups)),
+            ("materials", list(&self.materials)),
+            ("warnings", list(&self.warnings)),
+            ("parse_time_ms", (self.parse_time.as_secs_f64() * 1000.0).to_string()),
+        ];
+        let fields: Vec<String> = fields.iter().map(|(name, value)| format!("  \"{name}\": {value}")).collect();
+        format!("{{\n{}\n}}", fields.join(",\n"))
+    }
+}
+
+fn json_string(value: &str) -> String {
+    let mut json = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+impl fmt::Display for Info {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", self.path)?;
+        writeln!(f, "  vertices: {} ({} positions, {} normals, {} texture coordinates{})", self.vertices, self.positions, self.normals,
+            self.texture_coordinates, self.generated_uvs.map_or(String::new(), |projection| format!(" made by {projection} projection")))?;
+        writeln!(f, "  triangles: {}, segments: {}, points: {}", self.triangles, self.segments, self.points)?;
+        match self.bounds {
+            Some(bounds) => {
+                writeln!(f, "  bounds: {:?} to {:?}", bounds.min, bounds.max)?;
+                writeln!(f, "  center: {:?}", bounds.center())?;
+            },
+            None => writeln!(f, "  bounds: none")?,
+        }
+        writeln!(f, "  surface area: {}", self.area)?;
+        match self.volume {
+            Some(volume) => writeln!(f, "  volume: {volume}")?,
+            None => writeln!(f, "  volume: none, the mesh isn't closed or its winding isn't consistent")?,
+        }
+        let names = |names: &[String]| if names.is_empty() { String::from("none") } else { names.join(", ") };
+        writeln!(f, "  groups: {}", names(&self.groups))?;
+        writeln!(f, "  materials: {}", names(&self.materials))?;
+        for warning in self.warnings.iter() {
+            writeln!(f, "  warning: {warning}")?;
+        }
+        write!(f, "  parse time: {:?}", self.parse_time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use ::gltf::json::{deserialize::from_str, Value};
+    use super::Info;
+    use crate::parsing::{parsing, ParseOptions};
+
+    const TETRAHEDRON: &str = "v 0 0 0\nv 1 0 0\nv 0 1 0\nv 0 0 1\nf 1 3 2\nf 1 2 4\nf 2 3 4\nf 3 1 4\n";
+
+    fn info(obj: &str) -> Info {
+        Info::new("model.obj", &parsing(obj.as_bytes(), &ParseOptions::default()).unwrap(), Duration::from_millis(3))
+    }
+
+    #[test]
+    fn volume() {
+        assert_eq!(info(TETRAHEDRON).volume, Some(1.0 / 6.0));
+        // closed, but a face is flipped
+        assert_eq!(info(&TETRAHEDRON.replace("f 2 3 4", "f 2 4 3")).volume, None);
+        assert_eq!(info(&TETRAHEDRON.replace("f 3 1 4\n", "")).volume, None);
+        // the first corner again as -0
+        let info = info(&TETRAHEDRON.replace("f 3 1 4", "v -0 0 -0\nf 3 5 4"));
+        assert_eq!((info.positions, info.volume), (4, Some(1.0 / 6.0)));
+    }
+
+    #[test]
+    fn empty() {
+        let info = info("o nothing\n");
+        assert_eq!((info.area.to_bits(), info.bounds), (0.0f32.to_bits(), None));
+        let text = info.to_string();
+        assert!(text.contains("  surface area: 0\n") && text.contains("  bounds: none\n"), "{text}");
+        let json: Value = from_str(&info.to_json()).unwrap();
+        assert_eq!((&json["bounds"], &json["center"]), (&Value::Null, &Value::Null));
+    }
+
+    #[test]
+    fn json() {
+        let mut info = info(&format!("o \"quoted\" back\\slash\n{TETRAHEDRON}"));
+        info.path = String::from("dir\\tab\there \"new\nline\" \u{1}.obj");
+        info.warnings.push(String::from("é ✓"));
+        let json: Value = from_str(&info.to_json()).unwrap();
+        assert_eq!(json["path"], info.path.as_str());
+        assert_eq!(json["groups"][0], "\"quoted\" back\\slash");
+        assert_eq!(json["warnings"][0], "é ✓");
+        assert_eq!(json["vertices"], info.vertices);
+        assert_eq!(json["positions"], 4);
+        assert_eq!(json["triangles"], 4);
+        assert_eq!(json["generated_uvs"], "planar");
+        assert_eq!(json["bounds"]["max"], Value::from(vec![1, 1, 1]));
+        assert!((json["volume"].as_f64().unwrap() - 1.0 / 6.0).abs() < 1e-6);
+        assert_eq!(json["parse_time_ms"].as_f64(), Some(3.0));
+        assert_eq!(json.as_object().unwrap().len(), 17);
+    }
+}
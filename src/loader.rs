@@ -0,0 +1,243 @@
+use std::{error::Error, fmt, fs::{self, File}, io::{self, BufRead, BufReader}, path::{Path, PathBuf}};
+use crate::{
+    cache,
+    gltf::parsing_gltf,
+    material::parse_mtl,
+    off::parsing_off,
+    parallel::parsing_parallel,
+    ply::parsing_ply,
+    parsing::{parsing, Model, ParseError, ParseOptions, Warning},
+    stl::parsing_stl,
+};
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, ParseError),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (path, e): (&PathBuf, &dyn fmt::Display) = match self {
+            LoadError::Io(path, e) => (path, e),
+            LoadError::Parse(path, e) => (path, e),
+        };
+        write!(f, "{}: {e}", path.display())
+    }
+}
+
+impl Error for LoadError {}
+
+/// The mesh formats scop reads
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Format {
+    Obj,
+    Stl,
+    Ply,
+    Gltf,
+    Off,
+}
+
+impl Format {
+    /// From the file extension, or from the first bytes when it doesn't tell
+    pub fn detect(path: &Path, head: &[u8]) -> Format {
+        let extension = path.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase);
+        match extension.as_deref() {
+            Some("obj") => Format::Obj,
+            Some("stl") => Format::Stl,
+            Some("ply") => Format::Ply,
+            Some("gltf" | "glb") => Format::Gltf,
+            Some("off") => Format::Off,
+            // glb magic, or the json of a gltf
+            _ if head.starts_with(b"glTF") || head.trim_ascii_start().starts_with(b"{") => Format::Gltf,
+            _ if head.starts_with(b"ply\n") || head.starts_with(b"ply\r\n") => Format::Ply,
+            // obj files are text, binary stl files are full of zeros
+            _ if head.starts_with(b"solid") || head.contains(&0) => Format::Stl,
+            // OFF, COFF, NOFF... maybe after comments
+            _ if head.split(|&b| b == b'\n')
+                .map(|line| line.trim_ascii())
+                .find(|line| !line.is_empty() && !line.starts_with(b"#"))
+                .is_some_and(|line| line.split(u8::is_ascii_whitespace).next().is_some_and(|keyword| keyword.windows(3).any(|w| w == b"OFF"))) => Format::Off,
+            _ => Format::Obj,
+        }
+    }
+}
+
+/// Read and parse a mesh file along with the material libraries it uses,
+/// `-` reads the mesh from the standard input.
+/// Missing or malformed libraries and missing materials are reported as warnings.
+/// With more than one thread an obj is read whole and parsed in parallel.
+/// With a `cache` directory, a binary copy of the model is kept there to skip parsing
+/// the file again while it and the files it refers to are unchanged.
+pub fn load(path: &Path, options: &ParseOptions, cache: Option<&Path>) -> Result<Model, LoadError> {
+    // the standard input can't be told apart from one run to the next
+    let cache = cache.filter(|_| path != Path::new("-"));
+    if let Some(model) = cache.and_then(|dir| cache::read(dir, path, options)) {
+        return Ok(model);
+    }
+    let reader: Box<dyn BufRead> = match path == Path::new("-") {
+        true => Box::new(io::stdin().lock()),
+        false => Box::new(BufReader::new(File::open(path).map_err(|e| LoadError::Io(path.to_path_buf(), e))?)),
+    };
+    let (mut model, dependencies) = read(reader, path, options)?;
+    if let Some(dir) = cache {
+        if let Err(e) = cache::write(dir, path, options, &model, &dependencies) {
+            model.warnings.push(Warning { line: None, message: format!("mesh cache not saved: {e}") });
+        }
+    }
+    Ok(model)
+}
+
+// parse the mesh of `reader` and the libraries it names, `path` is where it comes from
+// and tells its format. Returns the paths of the other files it read with the model:
+// material libraries, glTF buffers and images.
+fn read(mut reader: impl BufRead, path: &Path, options: &ParseOptions) -> Result<(Model, Vec<PathBuf>), LoadError> {
+    let io_error = |e| LoadError::Io(path.to_path_buf(), e);
+    let format = Format::detect(path, reader.fill_buf().map_err(io_error)?);
+    // only a sequential obj parse doesn't need the whole file in memory
+    let parsed = match (format, options.threads > 1) {
+        (Format::Obj, false) => parsing(reader, options).map(|model| (model, Vec::new())),
+        (format, _) => {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).map_err(io_error)?;
+            match format {
+                Format::Obj => parsing_parallel(&bytes, options).map(|model| (model, Vec::new())),
+                Format::Stl => parsing_stl(&bytes, options).map(|model| (model, Vec::new())),
+                Format::Ply => parsing_ply(&bytes, options).map(|model| (model, Vec::new())),
+                Format::Off => parsing_off(&bytes, options).map(|model| (model, Vec::new())),
+                Format::Gltf => parsing_gltf(&bytes, path.parent().unwrap_or(Path::new("")), options),
+            }
+        },
+    };
+    let (mut model, mut dependencies) = parsed.map_err(|e| LoadError::Parse(path.to_path_buf(), e))?;
+
+    // material libraries of the standard input are looked for in the current directory
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let lib_paths: Vec<PathBuf> = model.material_libs.iter().map(|lib| dir.join(lib)).collect();
+    for lib_path in lib_paths.iter() {
+        // a library which can't be read or parsed only loses its materials
+        let parsed = fs::read_to_string(lib_path)
+            .map_err(|e| e.to_string())
+            .and_then(|mtl| parse_mtl(&mtl, lib_path.parent().unwrap_or(dir)).map_err(|e| e.to_string()));
+        match parsed {
+            Ok(materials) => model.materials.extend(materials),
+            Err(e) => model.warnings.push(Warning {
+                line: None,
+                message: format!("material library {}: {e}", lib_path.display()),
+            }),
+        }
+    }
+    let mut missing: Vec<String> = model.submeshes.iter()
+        .filter_map(|submesh| submesh.material.clone())
+        .filter(|name| !model.materials.iter().any(|m| &m.name == name))
+        .collect();
+    missing.sort();
+    missing.dedup();
+    model.warnings.extend(missing.into_iter().map(|name| Warning {
+        line: None,
+        message: format!("material {name:?} is not defined"),
+    }));
+    dependencies.extend(lib_paths);
+    Ok((model, dependencies))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::{Path, PathBuf}};
+    use super::{load, read};
+    use crate::parsing::{parsing, ParseError, ParseOptions};
+
+    // an empty directory of its own for every test
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("scop-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn broken_material_libraries() {
+        let dir = test_dir("broken-mtl");
+        fs::write(dir.join("good.mtl"), "newmtl red\nKd 1 0 0\n").unwrap();
+        fs::write(dir.join("bad.mtl"), "newmtl blue\nKd 0 0 x\n").unwrap();
+        let obj = "mtllib good.mtl bad.mtl missing.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl red\nf 1 2 3\nusemtl blue\nf 1 2 3\n";
+        fs::write(dir.join("model.obj"), obj).unwrap();
+        let model = load(&dir.join("model.obj"), &ParseOptions::default(), None).unwrap();
+        assert_eq!(model.materials.len(), 1);
+        assert_eq!(model.materials[0].diffuse, [1.0, 0.0, 0.0]);
+        let warnings: Vec<String> = model.warnings.iter().map(|warning| warning.to_string()).collect();
+        assert_eq!(warnings.len(), 3, "{warnings:?}");
+        assert!(warnings[0].starts_with(&format!("material library {}: line 2, column 8:", dir.join("bad.mtl").display())), "{}", warnings[0]);
+        assert!(warnings[1].starts_with(&format!("material library {}: ", dir.join("missing.mtl").display())), "{}", warnings[1]);
+        assert_eq!(warnings[2], "material \"blue\" is not defined");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn multi_word_material_names() {
+        let dir = test_dir("material-names");
+        fs::write(dir.join("model.mtl"), "newmtl dark  red wood\nKd 0.5 0 0\n").unwrap();
+        let obj = "mtllib model.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl dark red\twood\nf 1 2 3\n";
+        fs::write(dir.join("model.obj"), obj).unwrap();
+        let model = load(&dir.join("model.obj"), &ParseOptions::default(), None).unwrap();
+        assert!(model.warnings.is_empty(), "{:?}", model.warnings);
+        assert_eq!(model.materials.len(), 1);
+        assert_eq!(model.materials[0].name, "dark red wood");
+        assert_eq!(model.submeshes[0].material.as_deref(), Some("dark red wood"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    // gives `data` in pieces of `size` bytes, then fails
+    struct Pieces<'a> {
+        data: &'a [u8],
+        size: usize,
+    }
+
+    impl std::io::Read for Pieces<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.data.is_empty() {
+                return Err(std::io::Error::new(std::io::ErrorKind::ConnectionReset, "the pipe broke"));
+            }
+            let len = self.size.min(buf.len()).min(self.data.len());
+            buf[..len].copy_from_slice(&self.data[..len]);
+            self.data = &self.data[len..];
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn standard_input() {
+        let obj = "mtllib colors.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl red\nf 1 2 3\n";
+        let read = |bytes: &[u8]| read(std::io::Cursor::new(bytes.to_vec()), Path::new("-"), &ParseOptions::default());
+        // without an extension the format is told from the content
+        let (model, libs) = read(obj.as_bytes()).unwrap();
+        assert_eq!(model.indices.len(), 3);
+        // libraries are looked for in the current directory
+        assert_eq!(libs, [PathBuf::from("colors.mtl")]);
+        let stl = "solid t\nfacet normal 0 0 1\nouter loop\nvertex 0 0 0\nvertex 1 0 0\nvertex 0 1 0\nendloop\nendfacet\nendsolid t\n";
+        assert_eq!(read(stl.as_bytes()).unwrap().0.indices.len(), 3);
+        let off = "OFF\n3 1 0\n0 0 0\n1 0 0\n0 1 0\n3 0 1 2\n";
+        assert_eq!(read(off.as_bytes()).unwrap().0.indices.len(), 3);
+    }
+
+    #[test]
+    fn streamed_reads() {
+        let mut obj = String::from("# a long comment line to cross several reads of the buffer\n");
+        for i in 0..50 {
+            obj.push_str(&format!("v {i} 0 0\nv {i} 1 0\nv {i} 0 1\nf -3 -2 -1\n"));
+        }
+        let whole = parsing(obj.as_bytes(), &ParseOptions::default()).unwrap();
+        for size in [1, 3, 7, 64] {
+            // lines cut between two reads, the pipe breaks at the end
+            let reader = std::io::BufReader::with_capacity(size, Pieces { data: obj.as_bytes(), size: size + 1 });
+            match parsing(reader, &ParseOptions::default()) {
+                Err(ParseError::Io { line, kind, .. }) => assert_eq!((line, kind), (202, std::io::ErrorKind::ConnectionReset)),
+                result => panic!("unexpected {result:?}"),
+            }
+            let reader = std::io::BufReader::with_capacity(size, std::io::Cursor::new(obj.as_bytes()));
+            let streamed = parsing(reader, &ParseOptions::default()).unwrap();
+            assert_eq!(format!("{:?}", whole.vertices), format!("{:?}", streamed.vertices));
+            assert_eq!(whole.indices, streamed.indices);
+        }
+    }
+}
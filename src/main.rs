@@ -4,23 +4,48 @@ extern crate image;
 
 mod parsing;
 mod matrix;
+mod cache;
+mod normals;
+mod options;
 mod event;
-
-use std::{env, fs, process};
-use event::match_event_keyboard;
-use parsing::parsing;
+mod bounds;
+mod freeform;
+mod gltf;
+mod info;
+mod loader;
+mod material;
+mod off;
+mod parallel;
+mod ply;
+mod stl;
+mod tangents;
+mod tokenizer;
+mod triangulate;
+mod uv;
+mod validate;
+mod vector;
+mod writer;
+
+use std::{env, io::{self, Write}, process, path::{Path, PathBuf}};
+use event::{match_event_keyboard, toggle_group};
+use info::Info;
+use material::Material;
+use parsing::{Model, Normal, Scalars, Tangent, Vertex};
 use matrix::Matrix;
+use options::{Options, USAGE};
 use std::io::Cursor;
-use glium::{glutin, Surface, glutin::event::VirtualKeyCode};
+use glium::{glutin, Surface, glutin::event::VirtualKeyCode, texture::RawImage2d};
 
 const VERTEX_SHADER: &str = r#"
     #version 150
 
     in vec3 position;
     in vec2 tex_coords;
+    in vec4 color;
     in vec3 normal;
 
     out vec2 v_tex_coords;
+    out vec4 v_color;
     out vec3 v_normal;
     out vec3 v_position;
 
@@ -30,6 +55,7 @@ const VERTEX_SHADER: &str = r#"
 
     void main() {
         v_tex_coords = tex_coords;
+        v_color = color;
         mat4 modelview = view * model;
         v_normal = transpose(inverse(mat3(modelview))) * normal;
         gl_Position = perspective * modelview * vec4(position, 1.0);
@@ -42,15 +68,28 @@ const FRAGMENT_SHADER: &str = r#"
 
     in vec3 v_normal;
     in vec2 v_tex_coords;
+    in vec4 v_color;
     in vec3 v_position;
 
     out vec4 color;
 
     uniform vec3 u_light;
     uniform bool is_textured;
+    uniform bool is_colored;
     uniform bool is_enlightened;
     uniform sampler2D tex;
 
+    uniform bool has_material;
+    uniform vec3 u_ambient;
+    uniform vec3 u_diffuse;
+    uniform vec3 u_specular;
+    uniform float u_shininess;
+    uniform float u_opacity;
+    uniform int u_illum;
+    uniform sampler2D diffuse_map;
+    uniform sampler2D specular_map;
+    uniform sampler2D opacity_map;
+
     vec4 get_enlightened_color(vec4 base_color, float strength) {
         float diffuse = max(dot(normalize(v_normal), normalize(u_light)), 0.0);
         vec3 camera_dir = normalize(-v_position);
@@ -63,6 +102,22 @@ const FRAGMENT_SHADER: &str = r#"
         return vec4(dark_color + diffuse * regular_color + specular * specular_color, 1.0);
     }
 
+    // illum 0 is a flat color, 1 has no highlights, 2 and above are fully lit
+    vec4 get_material_color(vec4 base_color) {
+        if (u_illum == 0) {
+            return base_color;
+        }
+        float diffuse = max(dot(normalize(v_normal), normalize(u_light)), 0.0);
+        vec3 lit_color = u_ambient * base_color.rgb + diffuse * base_color.rgb;
+        if (u_illum > 1) {
+            vec3 camera_dir = normalize(-v_position);
+            vec3 half_direction = normalize(normalize(u_light) + camera_dir);
+            float specular = pow(max(dot(half_direction, normalize(v_normal)), 0.0), max(u_shininess, 1.0));
+            lit_color += specular * u_specular * texture(specular_map, v_tex_coords).rgb;
+        }
+        return vec4(lit_color, base_color.a);
+    }
+
     void main() {
         vec4 raw_color;
         float strength;
@@ -70,13 +125,25 @@ const FRAGMENT_SHADER: &str = r#"
         if (is_textured) {
             raw_color = texture(tex, v_tex_coords);
             strength = 0.4;
+        } else if (is_colored) {
+            raw_color = v_color;
+            strength = 0.02;
+        } else if (has_material) {
+            raw_color = vec4(u_diffuse, 1.0) * texture(diffuse_map, v_tex_coords);
+            strength = 0.0;
         } else {
             float grey = (float((gl_PrimitiveID) % 5) / 10.) * 0.4 + 0.02;
             raw_color = vec4(grey, grey, grey, 1.0);
             strength = 0.02;
         }
 
-        if (is_enlightened) {
+        if (has_material) {
+            raw_color.a *= u_opacity * texture(opacity_map, v_tex_coords).r;
+        }
+
+        if (is_enlightened && has_material) {
+            color = get_material_color(raw_color);
+        } else if (is_enlightened) {
             color = get_enlightened_color(raw_color, strength);
         } else {
             color = raw_color;
@@ -86,40 +153,94 @@ const FRAGMENT_SHADER: &str = r#"
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        println!("add an obj file in argument");
-        process::exit(1);
-    }
-    let (vertices, normals, indices_parsing, center) = match fs::read_to_string(args[1].clone()) {
-        Ok(contents) => {
-            match parsing(contents) {
-                Ok(x) => x,
-                Err(e) => {
-                    println!("{e}");
-                    process::exit(1)
-                }
-            }
+    let options = match Options::from_args(&args) {
+        Ok(options) => options,
+        Err(e) => {
+            println!("{e}\n{USAGE}");
+            process::exit(1);
         }
-        Err(_) => {
-            println!("Something went wrong when reading the file");
+    };
+    let start = std::time::Instant::now();
+    let cache = options.cache.then(cache::directory).flatten();
+    let model = match loader::load(Path::new(&options.path), &options.parse, cache.as_deref()) {
+        Ok(model) => model,
+        Err(e) => {
+            println!("{e}");
             process::exit(1)
         }
     };
+    if options.info {
+        let info = Info::new(&options.path, &model, start.elapsed());
+        let mut out = io::stdout().lock();
+        let written = match options.json {
+            true => writeln!(out, "{}", info.to_json()),
+            false => writeln!(out, "{info}"),
+        };
+        // piped to a reader which stopped early, like head
+        match written.and_then(|_| out.flush()) {
+            Err(e) if e.kind() != io::ErrorKind::BrokenPipe => {
+                eprintln!("{}: {e}", options.path);
+                process::exit(1);
+            },
+            _ => return,
+        }
+    }
+    for warning in model.warnings.iter() {
+        println!("{}: warning: {warning}", options.path);
+    }
+    if options.validate {
+        println!("{}", validate::validate(&model));
+        return
+    }
+    if let Some(output) = options.output {
+        if let Err(e) = writer::save(&model, Path::new(&output), options.precision) {
+            println!("{output}: {e}");
+            process::exit(1);
+        }
+        println!("saved to {output}");
+        return
+    }
+    println!("H checks the mesh and highlights its problems");
+    let groups = model.group_names();
+    println!("groups (toggle with 1-9, 0 shows all):");
+    for (i, name) in groups.iter().enumerate() {
+        println!("  {} {name}", i + 1);
+    }
+    if !model.scalars.is_empty() {
+        println!("vertex properties (false colors with F):");
+        for scalars in model.scalars.iter() {
+            println!("  {}", scalars.name);
+        }
+    }
+    // the geometry changes with the projection of the texture coordinates
+    let mut mesh = model;
+    let materials = std::mem::take(&mut mesh.materials);
+    let Model { bounds, sphere, has_colors, .. } = mesh;
 
     let event_loop = glutin::event_loop::EventLoop::new();
     let wb = glutin::window::WindowBuilder::new();
     let cb = glutin::ContextBuilder::new().with_depth_buffer(24);
     let display = glium::Display::new(wb, cb, &event_loop).unwrap();
 
-    let positions = glium::VertexBuffer::new(&display, &vertices).unwrap();
-    let normals = glium::VertexBuffer::new(&display, &normals).unwrap();
+    let mut positions = glium::VertexBuffer::new(&display, &mesh.vertices).unwrap();
+    let mut normals = glium::VertexBuffer::new(&display, &mesh.normals).unwrap();
+    let mut tangents = glium::VertexBuffer::new(&display, &mesh.tangents).unwrap();
     let mut indices = glium::IndexBuffer::new(
         &display,
         glium::index::PrimitiveType::TrianglesList,
-        &indices_parsing,
+        &mesh.indices,
     )
     .unwrap();
 
+    let elements = [
+        glium::IndexBuffer::new(&display, glium::index::PrimitiveType::LinesList, &mesh.lines).unwrap(),
+        glium::IndexBuffer::new(&display, glium::index::PrimitiveType::Points, &mesh.points).unwrap(),
+    ];
+
+    // problems of the mesh, looked for on the first H
+    let mut issues: Option<Issues> = None;
+    let mut show_issues = false;
+
     let program =
         glium::Program::from_source(&display, VERTEX_SHADER, FRAGMENT_SHADER, None).unwrap();
 
@@ -135,11 +256,50 @@ fn main() {
     let image = glium::texture::RawImage2d::from_raw_rgba_reversed(&image.into_raw(), image_dimensions);
     let texture = glium::texture::SrgbTexture2d::new(&display, image).unwrap(); 
 
+    // each submesh is drawn on its own, transparent ones last
+    let no_material = Material::new("");
+    let material_textures: Vec<MaterialTextures> = materials.iter()
+        .chain([&no_material])
+        .map(|material| MaterialTextures {
+            diffuse: glium::texture::SrgbTexture2d::new(&display, match &material.diffuse_image {
+                Some(image) => raw_image(image.clone()),
+                None => load_map(material.diffuse_map.as_ref()),
+            }).unwrap(),
+            specular: glium::texture::Texture2d::new(&display, load_map(material.specular_map.as_ref())).unwrap(),
+            opacity: glium::texture::Texture2d::new(&display, load_map(material.opacity_map.as_ref())).unwrap(),
+        })
+        .collect();
+    let mut draws: Vec<(std::ops::Range<usize>, Option<usize>, usize)> = mesh.submeshes.iter()
+        .map(|submesh| (
+            submesh.start..submesh.end,
+            submesh.material.as_ref().and_then(|name| materials.iter().position(|m| &m.name == name)),
+            groups.iter().position(|name| name == &submesh.name()).unwrap(),
+        ))
+        .collect();
+    draws.sort_by_key(|(_, material, _)| material.is_some_and(|i| is_transparent(&materials[i])));
+    // lines and points of every submesh, they are hidden with their group too
+    let element_draws: Vec<([std::ops::Range<usize>; 2], usize)> = mesh.submeshes.iter()
+        .map(|submesh| (
+            [submesh.line_start..submesh.line_end, submesh.point_start..submesh.point_end],
+            groups.iter().position(|name| name == &submesh.name()).unwrap(),
+        ))
+        .collect();
+    let mut visible_groups = vec![true; groups.len()];
+
+    // the whole model fits in the view at start, whatever its size
+    let center = bounds.center();
+    let fov: f32 = std::f32::consts::PI / 3.0;
+    let distance = (sphere.radius / (fov / 2.0).sin()).max(1.0);
+    let zfar = (4.0 * distance).max(1024.0);
+
     let mut rotations: (f32, usize, bool) = (0.0, 0, true);
     let mut object: [f32; 3] = [-center[0], -center[1], -center[2]];
-    let mut player: [f32; 6] = [0.0, 0.0, -5., 0.0, 0.0, 1.];
+    let mut player: [f32; 6] = [0.0, 0.0, -distance, 0.0, 0.0, 1.];
     let mut last_mouse_position: [f64; 2] = [0.0, 0.0];
     let mut is_textured: bool = false;
+    let mut is_colored: bool = has_colors;
+    // property of `scalars` shown in false colors
+    let mut false_color: Option<usize> = None;
     let mut is_enlightened: bool = false;
     let mut speed: f32 = 0.1;
 
@@ -166,8 +326,6 @@ fn main() {
             let (width, height) = target.get_dimensions();
             let aspect_ratio = height as f32 / width as f32;
 
-            let fov: f32 = std::f32::consts::PI / 3.0;
-            let zfar = 1024.0;
             let znear = 0.1;
 
             let f = 1.0 / (fov / 2.0).tan();
@@ -191,23 +349,91 @@ fn main() {
             ..Default::default()
         };
 
-        target
-            .draw(
-                (&positions, &normals),
-                &indices,
-                &program,
-                &uniform! {
-                    model: model.to_cols_array_2d(),
-                    view: view,
-                    perspective: perspective,
-                    u_light: light,
-                    tex: &texture,
-                    is_textured: is_textured,
-                    is_enlightened: is_enlightened,
-                },
-                &params,
-            )
-            .unwrap();
+        for (range, material_id, _) in draws.iter().filter(|(_, _, group)| visible_groups[*group]) {
+            let (material, textures) = match material_id {
+                Some(i) => (&materials[*i], &material_textures[*i]),
+                None => (&no_material, &material_textures[materials.len()]),
+            };
+            let params = match is_transparent(material) {
+                true => glium::DrawParameters { blend: glium::Blend::alpha_blending(), ..params.clone() },
+                false => params.clone(),
+            };
+            target
+                .draw(
+                    (&positions, &normals, &tangents),
+                    indices.slice(range.clone()).unwrap(),
+                    &program,
+                    &uniform! {
+                        model: model.to_cols_array_2d(),
+                        view: view,
+                        perspective: perspective,
+                        u_light: light,
+                        tex: &texture,
+                        is_textured: is_textured,
+                        is_colored: is_colored,
+                        is_enlightened: is_enlightened,
+                        has_material: material_id.is_some(),
+                        u_ambient: material.ambient,
+                        u_diffuse: material.diffuse,
+                        u_specular: material.specular,
+                        u_shininess: material.shininess,
+                        u_opacity: material.opacity,
+                        u_illum: material.illum as i32,
+                        diffuse_map: &textures.diffuse,
+                        specular_map: &textures.specular,
+                        opacity_map: &textures.opacity,
+                    },
+                    &params,
+                )
+                .unwrap();
+        }
+        // polylines and points have no normal, they are never lit
+        let params = glium::DrawParameters { point_size: Some(4.0), ..params };
+        // the problems are drawn in their colors, over everything else
+        let issue_params = glium::DrawParameters {
+            depth: glium::Depth { test: glium::draw_parameters::DepthTest::Overwrite, ..Default::default() },
+            ..params.clone()
+        };
+        let issue_draws = issues.iter()
+            .filter(|_| show_issues)
+            .flat_map(|issues| issues.elements.iter().map(move |element| (&issues.positions, &issues.normals, &issues.tangents, element)))
+            .map(|(positions, normals, tangents, element)| ((positions, normals, tangents), element.slice(..).unwrap(), &issue_params, false, true));
+        let drawn = element_draws.iter()
+            .filter(|(_, group)| visible_groups[*group])
+            .flat_map(|(ranges, _)| elements.iter().zip(ranges.clone()))
+            .map(|(element, range)| ((&positions, &normals, &tangents), element.slice(range).unwrap(), &params, is_textured, is_colored))
+            .chain(issue_draws)
+            .filter(|(_, element, _, _, _)| element.len() > 0);
+        for (buffers, element, params, is_textured, is_colored) in drawn {
+            target
+                .draw(
+                    buffers,
+                    element,
+                    &program,
+                    &uniform! {
+                        model: model.to_cols_array_2d(),
+                        view: view,
+                        perspective: perspective,
+                        u_light: light,
+                        tex: &texture,
+                        is_textured: is_textured,
+                        is_colored: is_colored,
+                        is_enlightened: false,
+                        has_material: false,
+                        u_ambient: no_material.ambient,
+                        u_diffuse: no_material.diffuse,
+                        u_specular: no_material.specular,
+                        u_shininess: no_material.shininess,
+                        u_opacity: no_material.opacity,
+                        u_illum: no_material.illum as i32,
+                        diffuse_map: &material_textures[materials.len()].diffuse,
+                        specular_map: &material_textures[materials.len()].specular,
+                        opacity_map: &material_textures[materials.len()].opacity,
+                    },
+                    params,
+                )
+                .unwrap();
+        }
         target.finish().unwrap();
 
         match event {
@@ -224,17 +450,64 @@ fn main() {
                                     glium::index::PrimitiveType::TrianglesList => glium::IndexBuffer::new(
                                         &display,
                                         glium::index::PrimitiveType::LinesList,
-                                        &indices_parsing,
+                                        &mesh.indices,
                                     )
                                     .unwrap(),
                                     _ => glium::IndexBuffer::new(
                                         &display,
                                         glium::index::PrimitiveType::TrianglesList,
-                                        &indices_parsing,
+                                        &mesh.indices,
                                     )
                                     .unwrap()
                                 }
                             },
+                            // cycle through the vertex properties in false colors, then back to the vertex colors
+                            VirtualKeyCode::F if !mesh.scalars.is_empty() => {
+                                false_color = match false_color {
+                                    None => Some(0),
+                                    Some(i) if i + 1 < mesh.scalars.len() => Some(i + 1),
+                                    Some(_) => None,
+                                };
+                                match false_color {
+                                    Some(i) => {
+                                        positions.write(&false_colors(&mesh.vertices, &mesh.scalars[i]));
+                                        is_colored = true;
+                                        println!("false colors: {}", mesh.scalars[i].name);
+                                    },
+                                    None => {
+                                        positions.write(&mesh.vertices);
+                                        is_colored = has_colors;
+                                        println!("false colors off");
+                                    },
+                                }
+                            },
+                            VirtualKeyCode::H => {
+                                show_issues = !show_issues;
+                                if show_issues && issues.is_none() {
+                                    issues = Some(Issues::new(&display, &mesh));
+                                }
+                            },
+                            // cycle through the projections of generated texture coordinates
+                            VirtualKeyCode::U => match mesh.uv {
+                                Some(projection) => {
+                                    uv::project(&mut mesh, projection.next());
+                                    positions = match false_color {
+                                        Some(i) => glium::VertexBuffer::new(&display, &false_colors(&mesh.vertices, &mesh.scalars[i])),
+                                        None => glium::VertexBuffer::new(&display, &mesh.vertices),
+                                    }
+                                    .unwrap();
+                                    normals = glium::VertexBuffer::new(&display, &mesh.normals).unwrap();
+                                    tangents = glium::VertexBuffer::new(&display, &mesh.tangents).unwrap();
+                                    indices = glium::IndexBuffer::new(&display, indices.get_primitives_type(), &mesh.indices).unwrap();
+                                    println!("texture coordinates: {} projection", projection.next().name());
+                                },
+                                None => println!("the model has its own texture coordinates"),
+                            },
+                            // show/hide groups
+                            VirtualKeyCode::Key0 | VirtualKeyCode::Key1 | VirtualKeyCode::Key2
+                            | VirtualKeyCode::Key3 | VirtualKeyCode::Key4 | VirtualKeyCode::Key5
+                            | VirtualKeyCode::Key6 | VirtualKeyCode::Key7 | VirtualKeyCode::Key8
+                            | VirtualKeyCode::Key9 => toggle_group(key, &mut visible_groups, &groups),
                             _ => match_event_keyboard(
                                 key,
                                 control_flow,
@@ -243,6 +516,7 @@ fn main() {
                                 &mut player,
                                 &mut rotations,
                                 &mut is_textured,
+                                &mut is_colored,
                                 &mut is_enlightened,
                                 &center
                             )
@@ -279,6 +553,76 @@ fn main() {
     });
 }
 
+// textures of a material, the maps it doesn't have are plain white
+struct MaterialTextures {
+    diffuse: glium::texture::SrgbTexture2d,
+    specular: glium::texture::Texture2d,
+    opacity: glium::texture::Texture2d,
+}
+
+// problems of the mesh drawn over it
+struct Issues {
+    positions: glium::VertexBuffer<Vertex>,
+    normals: glium::VertexBuffer<Normal>,
+    tangents: glium::VertexBuffer<Tangent>,
+    elements: [glium::IndexBuffer<u32>; 3],
+}
+
+impl Issues {
+    // the checks take a while on large meshes, they only run when asked for
+    fn new(display: &glium::Display, model: &Model) -> Self {
+        let report = validate::validate(model);
+        match report.is_clean() {
+            true => println!("the mesh has no problems"),
+            false => println!("the mesh has problems (details with --validate):\n{report}"),
+        }
+        let overlay = validate::overlay(model, &report);
+        let count = overlay.vertices.len();
+        Issues {
+            positions: glium::VertexBuffer::new(display, &overlay.vertices).unwrap(),
+            normals: glium::VertexBuffer::new(display, &vec![Normal { normal: (0.0, 0.0, 0.0) }; count]).unwrap(),
+            tangents: glium::VertexBuffer::new(display, &vec![Tangent { tangent: [0.0; 4] }; count]).unwrap(),
+            elements: [
+                glium::IndexBuffer::new(display, glium::index::PrimitiveType::TrianglesList, &overlay.triangles).unwrap(),
+                glium::IndexBuffer::new(display, glium::index::PrimitiveType::LinesList, &overlay.lines).unwrap(),
+                glium::IndexBuffer::new(display, glium::index::PrimitiveType::Points, &overlay.points).unwrap(),
+            ],
+        }
+    }
+}
+
+fn is_transparent(material: &Material) -> bool {
+    material.opacity < 1.0 || material.opacity_map.is_some()
+}
+
+fn raw_image(image: image::RgbaImage) -> RawImage2d<'static, u8> {
+    let dimensions = image.dimensions();
+    RawImage2d::from_raw_rgba_reversed(&image.into_raw(), dimensions)
+}
+
+fn load_map(path: Option<&PathBuf>) -> RawImage2d<'static, u8> {
+    if let Some(path) = path {
+        match image::open(path) {
+            Ok(img) => return raw_image(img.to_rgba8()),
+            Err(e) => println!("{}: warning: {e}", path.display()),
+        }
+    }
+    RawImage2d::from_raw_rgba(vec![255u8; 4], (1, 1))
+}
+
+// blue for the lowest values, through green, to red for the highest
+fn false_colors(vertices: &[Vertex], scalars: &Scalars) -> Vec<Vertex> {
+    let (lo, hi) = scalars.values.iter().fold((f32::MAX, f32::MIN), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+    let range = if hi > lo { hi - lo } else { 1.0 };
+    vertices.iter()
+        .zip(scalars.values.iter())
+        .map(|(vertex, &value)| {
+            let t = 2.0 * (value - lo) / range - 1.0;
+            Vertex { color: [t.clamp(0.0, 1.0), 1.0 - t.abs(), (-t).clamp(0.0, 1.0), 1.0], ..*vertex }
+        })
+        .collect()
+}
+
 fn view_matrix(position: &[f32; 3], direction: &[f32; 3], up: &[f32; 3]) -> [[f32; 4]; 4] {
     let f = {
         let f = direction;
@@ -0,0 +1,173 @@
+use std::path::{Path, PathBuf};
+use image::RgbaImage;
+use crate::{parsing::{Location, ParseError}, tokenizer::tokens};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Material {
+    pub name: String,
+    /// Ka
+    pub ambient: [f32; 3],
+    /// Kd
+    pub diffuse: [f32; 3],
+    /// Ks
+    pub specular: [f32; 3],
+    /// Ns
+    pub shininess: f32,
+    /// d, or 1 - Tr
+    pub opacity: f32,
+    pub illum: u32,
+    pub diffuse_map: Option<PathBuf>,
+    pub specular_map: Option<PathBuf>,
+    pub bump_map: Option<PathBuf>,
+    pub opacity_map: Option<PathBuf>,
+    /// A diffuse map embedded in the model file, used in place of `diffuse_map`
+    pub diffuse_image: Option<RgbaImage>,
+}
+
+impl Material {
+    pub fn new(name: &str) -> Self {
+        Material {
+            name: name.to_string(),
+            ambient: [0.2, 0.2, 0.2],
+            diffuse: [0.8, 0.8, 0.8],
+            specular: [0.0, 0.0, 0.0],
+            shininess: 0.0,
+            opacity: 1.0,
+            illum: 2,
+            diffuse_map: None,
+            specular_map: None,
+            bump_map: None,
+            opacity_map: None,
+            diffuse_image: None,
+        }
+    }
+}
+
+// values taken by the options of a map statement (`map_Kd -s 1 1 1 file.png`):
+// at most that many, and whether they are numbers which may be left out
+fn map_option_len(option: &str) -> (usize, bool) {
+    match option {
+        // `u [v [w]]`
+        "-o" | "-s" | "-t" => (3, true),
+        "-mm" => (2, false),
+        _ => (1, false),
+    }
+}
+
+// the file of a map statement, everything after the options so its name may have spaces
+fn map_file<'a>(line: &'a str, chunk: &[&'a str]) -> Option<&'a str> {
+    let mut i = 1;
+    while i < chunk.len() && chunk[i].starts_with('-') {
+        let (len, optional) = map_option_len(chunk[i]);
+        i += 1 + chunk[i + 1..].iter()
+            .take(len)
+            .take_while(|value| !optional || value.parse::<f32>().is_ok())
+            .count();
+    }
+    // tokens are sub slices of the line
+    let offset = |token: &str| token.as_ptr() as usize - line.as_ptr() as usize;
+    let (first, last) = (chunk.get(i)?, chunk.last()?);
+    Some(&line[offset(first)..offset(last) + last.len()])
+}
+
+/// Parse a material library, texture paths are resolved relative to `dir`.
+/// Statements we don't render (Ke, Ni, Tf, PBR extensions...) are skipped.
+pub fn parse_mtl(mtl: &str, dir: &Path) -> Result<Vec<Material>, ParseError> {
+    let mut materials: Vec<Material> = Vec::new();
+    for (nb, line) in mtl.split('\n').enumerate() {
+        let chunk = tokens(line).collect::<Vec<&str>>();
+        let at = |token: &str| Location::new(nb + 1, line, token);
+        let floats = |from: usize, expected: usize| -> Result<Vec<f32>, ParseError> {
+            let values = chunk[from..].iter()
+                .map(|x| x.parse::<f32>().map_err(|_| ParseError::BadFloat(at(x))))
+                .collect::<Result<Vec<f32>, ParseError>>()?;
+            match values.len() >= expected {
+                true => Ok(values),
+                false => Err(ParseError::WrongArity { at: at(chunk[0]), expected, found: values.len() }),
+            }
+        };
+        let keyword = match chunk.first() {
+            Some(&"newmtl") => {
+                // the rest of the line, like `usemtl`
+                if chunk.len() < 2 {
+                    return Err(ParseError::WrongArity { at: at(chunk[0]), expected: 1, found: 0 });
+                }
+                materials.push(Material::new(&chunk[1..].join(" ")));
+                continue
+            },
+            Some(keyword) => *keyword,
+            None => continue,
+        };
+        // everything else describes the last declared material
+        let Some(material) = materials.last_mut() else {
+            return Err(ParseError::UnsupportedDirective(at(keyword)));
+        };
+        match keyword {
+            "Ka" | "Kd" | "Ks" => {
+                // a single value is used for the three channels
+                let rgb = floats(1, 1)?;
+                let rgb = [rgb[0], *rgb.get(1).unwrap_or(&rgb[0]), *rgb.get(2).unwrap_or(&rgb[0])];
+                match keyword {
+                    "Ka" => material.ambient = rgb,
+                    "Kd" => material.diffuse = rgb,
+                    _ => material.specular = rgb,
+                }
+            },
+            "Ns" => material.shininess = floats(1, 1)?[0],
+            // `d -halo 0.5`
+            "d" => material.opacity = floats(chunk.len().max(2) - 1, 1)?[0],
+            "Tr" => material.opacity = 1.0 - floats(1, 1)?[0],
+            "illum" => material.illum = floats(1, 1)?[0] as u32,
+            "map_Kd" | "map_Ks" | "map_Bump" | "map_bump" | "bump" | "map_d" => {
+                let file = map_file(line, &chunk).ok_or_else(|| ParseError::WrongArity { at: at(keyword), expected: 1, found: 0 })?;
+                let file = dir.join(file);
+                match keyword {
+                    "map_Kd" => material.diffuse_map = Some(file),
+                    "map_Ks" => material.specular_map = Some(file),
+                    "map_d" => material.opacity_map = Some(file),
+                    _ => material.bump_map = Some(file),
+                }
+            },
+            _ => {}
+        }
+    }
+    Ok(materials)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+    use super::{parse_mtl, Material};
+    use crate::parsing::ParseError;
+
+    fn material(mtl: &str) -> Material {
+        parse_mtl(&format!("newmtl m\n{mtl}"), Path::new("textures")).unwrap().remove(0)
+    }
+
+    #[test]
+    fn map_options() {
+        let diffuse = |statement: &str| material(statement).diffuse_map.unwrap();
+        let file = PathBuf::from("textures/tex.png");
+        assert_eq!(diffuse("map_Kd tex.png"), file);
+        // -o, -s and -t take one to three numbers
+        assert_eq!(diffuse("map_Kd -s 2 2 tex.png"), file);
+        assert_eq!(diffuse("map_Kd -s 2 tex.png"), file);
+        assert_eq!(diffuse("map_Kd -o 0.5 0.5 0 -s 2 2 2 tex.png"), file);
+        assert_eq!(diffuse("map_Kd -t 1 -blendu on -mm 0 1 -clamp on tex.png # tiled"), file);
+        assert_eq!(diffuse("map_Kd -imfchan r -bm 0.5 \ttex.png\r"), file);
+        assert_eq!(material("map_Bump -bm 0.3 normals.png").bump_map.unwrap(), PathBuf::from("textures/normals.png"));
+        // the name is the rest of the line
+        assert_eq!(diffuse("map_Kd -s 2 2 my texture.png"), PathBuf::from("textures/my texture.png"));
+        assert_eq!(diffuse("map_Kd  wood  grain.png  "), PathBuf::from("textures/wood  grain.png"));
+    }
+
+    #[test]
+    fn map_without_file() {
+        for statement in ["map_Kd", "map_Kd -s 1 1 1", "map_Kd -clamp on"] {
+            match parse_mtl(&format!("newmtl m\n{statement}\n"), Path::new("")) {
+                Err(ParseError::WrongArity { at, .. }) => assert_eq!((at.line, at.token.as_str()), (2, "map_Kd")),
+                result => panic!("{statement}: unexpected {result:?}"),
+            }
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use crate::vector::{cross, dot, length, normalize, sub};
+
+// angle of a triangle at its corner `c`
+fn corner_angle(p: [[f32; 3]; 3], c: usize) -> f32 {
+    let a = normalize(sub(p[(c + 1) % 3], p[c]));
+    let b = normalize(sub(p[(c + 2) % 3], p[c]));
+    dot(a, b).clamp(-1.0, 1.0).acos()
+}
+
+/// Normal of every triangle corner, averaged with the corners of the other triangles
+/// sharing its position and smoothing group.
+/// Each face normal is weighted by the face's area and its angle at the shared position.
+/// Smoothing group 0 is flat shaded, and faces more than `crease_angle` degrees
+/// apart are never averaged so hard edges stay hard.
+pub fn generate_normals(positions: &[[f32; 3]], triangles: &[[usize; 3]], smoothing: &[u32], crease_angle: f32) -> Vec<[f32; 3]> {
+    let points: Vec<[[f32; 3]; 3]> = triangles.iter()
+        .map(|t| [positions[t[0]], positions[t[1]], positions[t[2]]])
+        .collect();
+    // the length of the cross product is twice the area, that's the area weighting
+    let face_normals: Vec<[f32; 3]> = points.iter().map(|p| cross(sub(p[1], p[0]), sub(p[2], p[0]))).collect();
+    let unit_normals: Vec<[f32; 3]> = face_normals.iter().map(|n| normalize(*n)).collect();
+
+    // triangles around each position, as (triangle, corner)
+    let mut starts = vec![0usize; positions.len() + 1];
+    for &p in triangles.iter().flatten() {
+        starts[p + 1] += 1;
+    }
+    for i in 1..starts.len() {
+        starts[i] += starts[i - 1];
+    }
+    let mut filled = starts.clone();
+    let mut around = vec![(0usize, 0usize); triangles.len() * 3];
+    for (t, triangle) in triangles.iter().enumerate() {
+        for (c, &p) in triangle.iter().enumerate() {
+            around[filled[p]] = (t, c);
+            filled[p] += 1;
+        }
+    }
+
+    let min_cos = crease_angle.to_radians().cos() - 1e-5;
+    let mut normals = Vec::with_capacity(triangles.len() * 3);
+    for (t, triangle) in triangles.iter().enumerate() {
+        for &p in triangle.iter() {
+            if smoothing[t] == 0 {
+                normals.push(unit_normals[t]);
+                continue
+            }
+            let mut sum = [0.0, 0.0, 0.0];
+            for &(other, c) in around[starts[p]..starts[p + 1]].iter() {
+                if smoothing[other] != smoothing[t] || dot(unit_normals[t], unit_normals[other]) < min_cos {
+                    continue
+                }
+                let weight = corner_angle(points[other], c);
+                let n = face_normals[other];
+                sum = [sum[0] + n[0] * weight, sum[1] + n[1] * weight, sum[2] + n[2] * weight];
+            }
+            normals.push(match length(sum) > 0.0 {
+                true => normalize(sum),
+                false => unit_normals[t],
+            });
+        }
+    }
+    normals
+}
+
+#[cfg(test)]
+mod tests {
+    use super::generate_normals;
+
+    // the three faces of a cube meeting at the origin, facing out
+    const CORNER: [[f32; 3]; 4] = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+    const FACES: [[usize; 3]; 3] = [[0, 2, 1], [0, 3, 2], [0, 1, 3]];
+
+    // normals at the origin of the three faces
+    fn at_origin(smoothing: &[u32], crease_angle: f32) -> Vec<[f32; 3]> {
+        generate_normals(&CORNER, &FACES, smoothing, crease_angle).into_iter().step_by(3).collect()
+    }
+
+    fn assert_near(found: &[[f32; 3]], expected: &[[f32; 3]]) {
+        let near = found.iter().zip(expected).all(|(a, b)| (0..3).all(|i| (a[i] - b[i]).abs() < 1e-5));
+        assert!(near && found.len() == expected.len(), "{found:?} != {expected:?}");
+    }
+
+    #[test]
+    fn crease_angle() {
+        let d = -1.0 / 3.0f32.sqrt();
+        assert_near(&at_origin(&[1, 1, 1], 180.0), &[[d, d, d]; 3]);
+        // 90 degrees apart is on the crease angle, under it the edges stay hard
+        assert_near(&at_origin(&[1, 1, 1], 90.0), &[[d, d, d]; 3]);
+        let flat = [[0.0, 0.0, -1.0], [-1.0, 0.0, 0.0], [0.0, -1.0, 0.0]];
+        assert_near(&at_origin(&[1, 1, 1], 89.0), &flat);
+        // the other corners are shared by two faces only
+        let h = -std::f32::consts::FRAC_1_SQRT_2;
+        assert_near(&generate_normals(&CORNER, &FACES, &[1, 1, 1], 180.0)[1..3], &[[h, 0.0, h], [0.0, h, h]]);
+    }
+
+    #[test]
+    fn smoothing_groups() {
+        let d = -std::f32::consts::FRAC_1_SQRT_2;
+        // group 0 is flat, faces of other groups are never averaged
+        assert_near(&at_origin(&[0, 0, 0], 180.0), &[[0.0, 0.0, -1.0], [-1.0, 0.0, 0.0], [0.0, -1.0, 0.0]]);
+        assert_near(&at_origin(&[3, 3, 4], 180.0), &[[d, 0.0, d], [d, 0.0, d], [0.0, -1.0, 0.0]]);
+        assert_near(&at_origin(&[1, 0, 1], 180.0), &[[0.0, d, d], [-1.0, 0.0, 0.0], [0.0, d, d]]);
+    }
+}
@@ -0,0 +1,202 @@
+use std::io;
+use crate::parsing::{build, Corner, Location, Model, ObjData, ParseError, ParseOptions, State};
+
+// what the header keyword (`STCNOFF`) says each vertex line holds
+#[derive(Copy, Clone, Debug, Default)]
+struct Layout {
+    textures: bool,
+    colors: bool,
+    normals: bool,
+}
+
+fn parse_layout(keyword: &str) -> Option<Layout> {
+    let prefix = keyword.strip_suffix("OFF")?;
+    let (textures, prefix) = match prefix.strip_prefix("ST") {
+        Some(rest) => (true, rest),
+        None => (false, prefix),
+    };
+    let (colors, prefix) = match prefix.strip_prefix('C') {
+        Some(rest) => (true, rest),
+        None => (false, prefix),
+    };
+    let (normals, prefix) = match prefix.strip_prefix('N') {
+        Some(rest) => (true, rest),
+        None => (false, prefix),
+    };
+    // 4OFF and nOFF are for other dimensions
+    prefix.is_empty().then_some(Layout { textures, colors, normals })
+}
+
+// a color as written, with or without its alpha
+type RawColor = ([f32; 3], Option<f32>);
+
+fn raw_color(values: &[f32]) -> RawColor {
+    ([values[0], values[1], values[2]], values.get(3).copied())
+}
+
+fn color((rgb, alpha): RawColor, scale: f32) -> [f32; 4] {
+    [rgb[0] / scale, rgb[1] / scale, rgb[2] / scale, alpha.map_or(1.0, |a| a / scale)]
+}
+
+fn is_integer(token: &str) -> bool {
+    token.parse::<i64>().is_ok()
+}
+
+/// Parse an OFF file and its COFF/NOFF/STOFF variants.
+/// Faces can have a color of their own, they are triangulated like obj faces.
+/// Colors go from 0 to 1, or from 0 to 255 when all of the file's are integers, as in Geomview.
+pub fn parsing_off(off: &[u8], options: &ParseOptions) -> Result<Model, ParseError> {
+    let text = std::str::from_utf8(off).map_err(|e| ParseError::Io {
+        line: off[..e.valid_up_to()].iter().filter(|&&b| b == b'\n').count() + 1,
+        kind: io::ErrorKind::InvalidData,
+        message: String::from("stream did not contain valid UTF-8"),
+    })?;
+    // lines without their comments, blank ones skipped
+    let mut lines = text.split_inclusive('\n')
+        .enumerate()
+        .map(|(nb, line)| (nb + 1, line, line.split('#').next().unwrap_or(line)))
+        .filter(|(_, _, content)| !content.trim().is_empty());
+
+    // the keyword, then the vertex, face and edge counts, some files put them on the same line
+    let Some((nb, line, content)) = lines.next() else {
+        return Err(ParseError::Invalid(String::from("empty file, no OFF header")));
+    };
+    let mut tokens: Vec<(usize, &str, &str)> = content.split_whitespace().map(|token| (nb, line, token)).collect();
+    let (_, _, keyword) = tokens.remove(0);
+    let split = keyword.find("OFF").map_or(keyword.len(), |i| i + 3);
+    let layout = parse_layout(&keyword[..split])
+        .ok_or_else(|| ParseError::UnsupportedDirective(Location::new(nb, line, keyword)))?;
+    // `OFF1234 5678 0` as written by some exporters
+    if split < keyword.len() {
+        tokens.insert(0, (nb, line, &keyword[split..]));
+    }
+    while tokens.len() < 2 {
+        let Some((nb, line, content)) = lines.next() else {
+            return Err(ParseError::WrongArity { at: Location::new(nb, line, keyword), expected: 2, found: tokens.len() });
+        };
+        tokens.extend(content.split_whitespace().map(|token| (nb, line, token)));
+    }
+    let mut counts = tokens.iter()
+        .take(2)
+        .map(|&(nb, line, token)| token.parse::<usize>().map_err(|_| ParseError::BadIndex(Location::new(nb, line, token))));
+    let (vertex_count, face_count) = (counts.next().unwrap()?, counts.next().unwrap()?);
+
+    let Some(element_count) = vertex_count.checked_add(face_count) else {
+        return Err(ParseError::Invalid(format!("{vertex_count} vertices and {face_count} faces announced, that's too many")));
+    };
+
+    let mut data = ObjData::default();
+    let state = State::default();
+    let mut corners: Vec<Corner> = Vec::new();
+    // the scale of the colors is known once they are all read
+    let (mut vertex_colors, mut face_colors): (Vec<RawColor>, Vec<RawColor>) = (Vec::new(), Vec::new());
+    let mut integer_colors = true;
+    for element in 0..element_count {
+        let is_face = element >= vertex_count;
+        let Some((nb, line, content)) = lines.next() else {
+            let what = if is_face { "faces" } else { "vertices" };
+            return Err(ParseError::Invalid(format!("{vertex_count} vertices and {face_count} faces announced, the file ends before its {what}")));
+        };
+        let at = |token: &str| Location::new(nb, line, token);
+        let tokens: Vec<&str> = content.split_whitespace().collect();
+        if !is_face {
+            let values = tokens.iter()
+                .map(|x| x.parse::<f32>().map_err(|_| ParseError::BadFloat(at(x))))
+                .collect::<Result<Vec<f32>, ParseError>>()?;
+            let normals = if layout.normals { 3 } else { 0 };
+            let textures = if layout.textures { 2 } else { 0 };
+            // the color may or may not have an alpha
+            let colors = values.len().saturating_sub(3 + normals + textures).clamp(3, 4);
+            let expected = 3 + normals + if layout.colors { colors } else { 0 } + textures;
+            if values.len() < expected {
+                return Err(ParseError::WrongArity { at: at(tokens.first().copied().unwrap_or(content.trim())), expected, found: values.len() });
+            }
+            data.positions.push([values[0], values[1], values[2]]);
+            if layout.normals {
+                data.normals.push((values[3], values[4], values[5]));
+            }
+            if layout.colors {
+                integer_colors &= tokens[3 + normals..3 + normals + colors].iter().all(|token| is_integer(token));
+                vertex_colors.push(raw_color(&values[3 + normals..3 + normals + colors]));
+            }
+            if layout.textures {
+                data.textures.push([values[expected - 2], values[expected - 1]]);
+            }
+            continue
+        }
+        let count = tokens[0].parse::<usize>().map_err(|_| ParseError::BadIndex(at(tokens[0])))?;
+        if count < 3 || tokens.len() < count + 1 {
+            return Err(ParseError::WrongArity { at: at(tokens[0]), expected: count.max(3), found: tokens.len() - 1 });
+        }
+        // a face color after the indices, a single value would be a color map index
+        let values = tokens[count + 1..].iter()
+            .map(|x| x.parse::<f32>().map_err(|_| ParseError::BadFloat(at(x))))
+            .collect::<Result<Vec<f32>, ParseError>>()?;
+        let face_color = match values.len() >= 3 {
+            true => {
+                let len = values.len().min(4);
+                integer_colors &= tokens[count + 1..count + 1 + len].iter().all(|token| is_integer(token));
+                face_colors.push(raw_color(&values[..len]));
+                Some(face_colors.len() - 1)
+            },
+            false => None,
+        };
+        corners.clear();
+        for &token in tokens[1..=count].iter() {
+            let position = token.parse::<usize>().map_err(|_| ParseError::BadIndex(at(token)))?;
+            if position >= vertex_count {
+                return Err(ParseError::IndexOutOfRange { at: at(token), len: vertex_count });
+            }
+            corners.push(Corner {
+                position,
+                texture: layout.textures.then_some(position),
+                normal: layout.normals.then_some(position),
+                color: face_color,
+            });
+        }
+        data.add_polygon(nb, &corners, &state);
+    }
+    let scale = if integer_colors { 255.0 } else { 1.0 };
+    data.colors = vertex_colors.into_iter().map(|raw| color(raw, scale)).collect();
+    data.face_colors = face_colors.into_iter().map(|raw| color(raw, scale)).collect();
+    Ok(build(data, options))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parsing_off;
+    use crate::parsing::{ParseError, ParseOptions};
+
+    fn colors(off: &str) -> Vec<[f32; 4]> {
+        parsing_off(off.as_bytes(), &ParseOptions::default()).unwrap().vertices.iter().map(|vertex| vertex.color).collect()
+    }
+
+    #[test]
+    fn color_scale() {
+        // integers go to 255, even when none is above 1
+        let integers = colors("COFF\n3 1 0\n0 0 0 255 0 0\n1 0 0 0 1 0 255\n0 1 0 0 0 0\n3 0 1 2\n");
+        assert_eq!(integers, [[1.0, 0.0, 0.0, 1.0], [0.0, 1.0 / 255.0, 0.0, 1.0], [0.0, 0.0, 0.0, 1.0]]);
+        // a single float makes them all floats
+        let floats = colors("COFF\n3 1 0\n0 0 0 1 0 0\n1 0 0 0 0.5 0 1\n0 1 0 0 0 1\n3 0 1 2\n");
+        assert_eq!(floats, [[1.0, 0.0, 0.0, 1.0], [0.0, 0.5, 0.0, 1.0], [0.0, 0.0, 1.0, 1.0]]);
+        // face colors share the scale of the file
+        let faces = colors("OFF\n4 2 0\n0 0 0\n1 0 0\n1 1 0\n0 1 0\n3 0 1 2 255 0 0\n3 0 2 3 0 1 0 128\n");
+        assert!(faces.contains(&[1.0, 0.0, 0.0, 1.0]));
+        assert!(faces.contains(&[0.0, 1.0 / 255.0, 0.0, 128.0 / 255.0]));
+    }
+
+    #[test]
+    fn counts() {
+        // on the keyword line, after comments
+        let model = parsing_off(b"# a triangle\nOFF3 1 0\n0 0 0\n1 0 0\n0 1 0\n3 0 1 2\n", &ParseOptions::default()).unwrap();
+        assert_eq!(model.indices, [0, 1, 2]);
+        let huge = format!("OFF\n{} 2 0\n", usize::MAX);
+        assert!(matches!(parsing_off(huge.as_bytes(), &ParseOptions::default()), Err(ParseError::Invalid(_))));
+        assert!(matches!(parsing_off(b"OFF\n3 1 0\n0 0 0\n1 0 0\n0 1 0\n", &ParseOptions::default()), Err(ParseError::Invalid(_))));
+        match parsing_off(b"OFF\n3 1 0\n0 0 0\n1 0 0\n0 1 0\n3 0 1 3\n", &ParseOptions::default()) {
+            Err(ParseError::IndexOutOfRange { at, len }) => assert_eq!((at.line, at.column, len), (6, 7, 3)),
+            result => panic!("unexpected {result:?}"),
+        }
+        assert!(matches!(parsing_off(b"4OFF\n", &ParseOptions::default()), Err(ParseError::UnsupportedDirective(_))));
+    }
+}
@@ -0,0 +1,89 @@
+use crate::{parsing::ParseOptions, uv::Projection};
+
+pub const USAGE: &str = "usage: scop [--crease <degrees>] [--threads <n>] [--uv <planar | box | spherical | cylindrical>] [--resolution <n>] [--no-cache] [--validate] [--output <out.obj> [--precision <decimals>]] <file.obj | .stl | .ply | .gltf | .glb | .off | ->
+       scop info [--json] [--crease <degrees>] [--threads <n>] [--uv <projection>] [--resolution <n>] <file>";
+
+pub struct Options {
+    pub path: String,
+    pub parse: ParseOptions,
+    /// keep a binary copy of parsed models, see `cache.rs`
+    pub cache: bool,
+    /// save the model as an obj instead of showing it
+    pub output: Option<String>,
+    /// decimals of the saved floats, all of them when None
+    pub precision: Option<usize>,
+    /// print the problems of the model instead of showing it
+    pub validate: bool,
+    /// `scop info`, print what's in the model instead of showing it
+    pub info: bool,
+    /// print the info as json
+    pub json: bool,
+}
+
+impl Options {
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut path = None;
+        let mut parse = ParseOptions::default();
+        let mut cache = true;
+        let mut output = None;
+        let mut precision = None;
+        let mut validate = false;
+        let info = args.get(1).is_some_and(|arg| arg == "info");
+        let mut json = false;
+        let mut args = args.iter().skip(if info { 2 } else { 1 });
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--crease" => {
+                    let value = args.next().ok_or(String::from("--crease needs an angle in degrees"))?;
+                    parse.crease_angle = value.parse::<f32>()
+                        .ok()
+                        .filter(|angle| (0.0..=180.0).contains(angle))
+                        .ok_or(format!("--crease must be between 0 and 180 degrees, found {value:?}"))?;
+                },
+                "--threads" => {
+                    let value = args.next().ok_or(String::from("--threads needs a number of threads"))?;
+                    parse.threads = value.parse::<usize>()
+                        .ok()
+                        .filter(|&threads| threads > 0)
+                        .ok_or(format!("--threads must be a positive number, found {value:?}"))?;
+                },
+                "--uv" => {
+                    let value = args.next().ok_or(String::from("--uv needs a projection"))?;
+                    parse.uv = Projection::from_name(value)
+                        .ok_or(format!("--uv must be planar, box, spherical or cylindrical, found {value:?}"))?;
+                },
+                "--resolution" => {
+                    let value = args.next().ok_or(String::from("--resolution needs a number of segments"))?;
+                    parse.resolution = value.parse::<usize>()
+                        .ok()
+                        .filter(|&resolution| resolution > 0)
+                        .ok_or(format!("--resolution must be a positive number of segments, found {value:?}"))?;
+                },
+                "--no-cache" => cache = false,
+                "--validate" => validate = true,
+                "--json" if info => json = true,
+                "--output" => output = Some(args.next().ok_or(String::from("--output needs a file"))?.clone()),
+                "--precision" => {
+                    let value = args.next().ok_or(String::from("--precision needs a number of decimals"))?;
+                    precision = Some(value.parse::<usize>().map_err(|_| format!("--precision must be a number of decimals, found {value:?}"))?);
+                },
+                option if option.starts_with('-') && option != "-" => return Err(format!("unknown option {option:?}")),
+                file => match path {
+                    None => path = Some(file.to_string()),
+                    Some(_) => return Err(format!("unexpected argument {file:?}")),
+                },
+            }
+        }
+        Ok(Options {
+            path: path.ok_or(String::from("add an obj, stl, ply, gltf or off file in argument"))?,
+            parse,
+            // info times a real parse
+            cache: cache && !info,
+            output,
+            precision,
+            validate,
+            info,
+            json,
+        })
+    }
+}
@@ -0,0 +1,227 @@
+use std::{io, thread};
+use crate::{
+    parsing::{build, LineParser, Model, ObjData, ParseError, ParseOptions, State},
+    tokenizer::{is_continued, tokens, Continuation},
+};
+
+// what a chunk declares, to know where the next one starts
+struct Summary<'a> {
+    // the chunk up to its first invalid utf-8 line
+    text: &'a str,
+    lines: usize,
+    // v, vt and vn statements
+    counts: [usize; 3],
+    // o, g, usemtl and s statements
+    state_lines: Vec<String>,
+    // line of the chunk which isn't valid utf-8
+    invalid: Option<usize>,
+}
+
+// split `obj` in about `count` pieces, cut after a line end which isn't in the middle of a statement
+fn split_lines(obj: &[u8], count: usize) -> Vec<&[u8]> {
+    let size = obj.len().div_ceil(count).max(1);
+    let mut chunks = Vec::with_capacity(count);
+    let mut rest = obj;
+    while !rest.is_empty() {
+        let mut cut = size.min(rest.len());
+        while cut < rest.len() {
+            let start = rest[..cut].iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+            cut = rest[cut..].iter().position(|&b| b == b'\n').map_or(rest.len(), |i| cut + i + 1);
+            if !is_continued(&String::from_utf8_lossy(&rest[start..cut])) {
+                break
+            }
+        }
+        let (chunk, next) = rest.split_at(cut);
+        chunks.push(chunk);
+        rest = next;
+    }
+    chunks
+}
+
+fn summarize(chunk: &[u8]) -> Summary<'_> {
+    let (text, invalid) = match std::str::from_utf8(chunk) {
+        Ok(text) => (text, None),
+        Err(e) => {
+            // only the lines before the invalid one are read
+            let valid = &chunk[..e.valid_up_to()];
+            let start = valid.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+            let text = std::str::from_utf8(&chunk[..start]).unwrap_or_default();
+            (text, Some(text.split_inclusive('\n').count() + 1))
+        }
+    };
+    let mut summary = Summary { text, lines: 0, counts: [0; 3], state_lines: Vec::new(), invalid };
+    let mut continuation = Continuation::default();
+    for line in text.split_inclusive('\n') {
+        summary.lines += 1;
+        let Some((_, statement)) = continuation.push(summary.lines, line) else {
+            continue
+        };
+        match tokens(statement).next() {
+            Some("v") => summary.counts[0] += 1,
+            Some("vt") => summary.counts[1] += 1,
+            Some("vn") => summary.counts[2] += 1,
+            Some("o" | "g" | "usemtl" | "s") => summary.state_lines.push(statement.to_string()),
+            _ => {}
+        }
+    }
+    summary
+}
+
+/// Parse an obj held in memory with `options.threads` threads.
+/// The file is cut into chunks of whole lines, a first pass counts what each chunk
+/// declares so every chunk can then be parsed on its own, with the indices and
+/// line numbers it would have in a sequential read.
+/// The result, errors included, is the same as `parsing`'s.
+pub fn parsing_parallel(obj: &[u8], options: &ParseOptions) -> Result<Model, ParseError> {
+    let chunks = split_lines(obj, options.threads.max(1));
+    let summaries: Vec<Summary> = thread::scope(|scope| {
+        let handles: Vec<_> = chunks.iter().map(|chunk| scope.spawn(|| summarize(chunk))).collect();
+        handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+    });
+
+    // where every chunk starts, the lines after an invalid one are never read
+    let mut starts = Vec::with_capacity(summaries.len());
+    let (mut base, mut line, mut state) = ([0; 3], 0, State::default());
+    for summary in summaries.iter() {
+        starts.push((base, line, state.clone()));
+        if summary.invalid.is_some() {
+            break
+        }
+        for (base, count) in base.iter_mut().zip(summary.counts) {
+            *base += count;
+        }
+        line += summary.lines;
+        let mut replay = LineParser::new([0; 3], 0, state);
+        for state_line in summary.state_lines.iter() {
+            // an invalid statement is reported by the chunk's own parser
+            let _ = replay.parse_line(state_line);
+        }
+        state = replay.state;
+    }
+
+    let parsed: Vec<Result<ObjData, ParseError>> = thread::scope(|scope| {
+        let handles: Vec<_> = summaries.iter().zip(starts)
+            .map(|(summary, (base, line, state))| scope.spawn(move || {
+                let mut parser = LineParser::new(base, line, state);
+                for text in summary.text.split_inclusive('\n') {
+                    parser.parse_line(text)?;
+                }
+                parser.finish()?;
+                match summary.invalid {
+                    Some(invalid) => Err(ParseError::Io {
+                        line: line + invalid,
+                        kind: io::ErrorKind::InvalidData,
+                        message: String::from("stream did not contain valid UTF-8"),
+                    }),
+                    None => Ok(parser.data),
+                }
+            }))
+            .collect();
+        handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+    });
+
+    // the first error in the file is the one a sequential read stops at
+    let mut data = ObjData::default();
+    for chunk in parsed {
+        data.append(chunk?);
+    }
+    Ok(build(data, options))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Write;
+    use super::{parsing_parallel, split_lines};
+    use crate::{parsing::{parsing, ParseOptions}, tokenizer::is_continued};
+
+    // the parallel parse gives the sequential one whatever the chunks, one line each at most
+    fn assert_same(obj: &str) {
+        let sequential = parsing(obj.as_bytes(), &ParseOptions::default());
+        for threads in [2, 3, 4, 7, 16, obj.len()] {
+            let parallel = parsing_parallel(obj.as_bytes(), &ParseOptions { threads, ..ParseOptions::default() });
+            match (&sequential, parallel) {
+                (Ok(sequential), Ok(parallel)) => {
+                    assert_eq!(format!("{:?}", sequential.vertices), format!("{:?}", parallel.vertices), "{threads} threads");
+                    assert_eq!(format!("{:?}", sequential.normals), format!("{:?}", parallel.normals), "{threads} threads");
+                    assert_eq!(sequential.indices, parallel.indices, "{threads} threads");
+                    assert_eq!(sequential.lines, parallel.lines, "{threads} threads");
+                    assert_eq!(sequential.points, parallel.points, "{threads} threads");
+                    assert_eq!(sequential.submeshes, parallel.submeshes, "{threads} threads");
+                    assert_eq!(sequential.warnings, parallel.warnings, "{threads} threads");
+                },
+                (Err(sequential), Err(parallel)) => assert_eq!(sequential, &parallel, "{threads} threads"),
+                (sequential, parallel) => panic!("{threads} threads: {sequential:?} and {parallel:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn state_across_chunks() {
+        let mut obj = String::from("mtllib a.mtl\n");
+        for i in 0..60 {
+            let (x, y) = ((i % 10) as f32, (i / 10) as f32);
+            writeln!(obj, "v {x} {y} {}\nv {} {y} 0.5\nv {x} {} 0", (x * y).sin(), x + 1.0, y + 1.0).unwrap();
+            // the state statements of a chunk change the faces of the next ones
+            match i % 7 {
+                0 => writeln!(obj, "o part{}", i % 3).unwrap(),
+                2 => writeln!(obj, "g group{}", i % 4).unwrap(),
+                4 => writeln!(obj, "usemtl m{}", i % 5).unwrap(),
+                5 => writeln!(obj, "s {}", i % 3).unwrap(),
+                6 => writeln!(obj, "s off").unwrap(),
+                _ => {},
+            }
+            writeln!(obj, "f {} {} {}\nl {} {}", 3 * i + 1, 3 * i + 2, 3 * i + 3, 3 * i + 1, 3 * i + 3).unwrap();
+        }
+        assert_same(&obj);
+    }
+
+    #[test]
+    fn negative_indices_across_chunks() {
+        let mut obj = String::from("vt 0 0\nvn 0 0 1\n");
+        for i in 0..40 {
+            writeln!(obj, "v {i} 0 0\nv {i} 1 0\nvt {i} 1\nv {i} 0 1\nvn 1 0 0").unwrap();
+            // up to the vertices of the previous rows, in other chunks
+            writeln!(obj, "f -3/-1/-1 -2/-2/-2 -1/-1/-1\nl -1 -4\np -2").unwrap();
+            if i > 2 {
+                writeln!(obj, "f -12 -8/-3 -1//-3").unwrap();
+            }
+        }
+        assert_same(&obj);
+    }
+
+    #[test]
+    fn continuations_across_chunks() {
+        let mut obj = String::new();
+        for i in 0..30 {
+            writeln!(obj, "v {i} 0 \\\n  0\nv {i} 1 0\nv \\\n{i} \\\n 0 1\ng \\\nrow{}\nf -3 \\\n -2 \\\n\n -1", i % 4).unwrap();
+        }
+        for chunks in [2, 5, 11, obj.len()] {
+            let split = split_lines(obj.as_bytes(), chunks);
+            assert_eq!(split.concat(), obj.as_bytes());
+            // an empty line ends a continued statement too
+            for chunk in split {
+                let chunk = String::from_utf8_lossy(chunk);
+                assert!(!chunk.split_inclusive('\n').next_back().is_some_and(is_continued), "{chunk:?}");
+            }
+        }
+        assert_same(&obj);
+        // the file ends in a continued statement
+        assert_same("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 \\\n3 \\");
+    }
+
+    #[test]
+    fn errors() {
+        // the first error of the file, even when a later chunk fails first
+        let triangle = "v 1 2 3\nv 1 2 3\nv 1 2 3\nf 1 2 3\n";
+        assert_same(&format!("{triangle}v 1 x 3\nf 1 2 9\n"));
+        assert_same(&format!("{triangle}f 1 2 9\nv 1 x 3\n"));
+        assert_same(&format!("{triangle}f 1 2 3 4\nf 1 2 \\\n 3\n"));
+        let invalid = [&b"v 1 2 3\nv 1 2 3\nv 1 2 3\nf 1 2 3\nv 1 \xff 3\nf 1 2 9\n"[..], b"v 1 2 3\nv 1 2 3\nv 1 2 3\nf 1 2 9\nv 1 \xff 3\n"];
+        for obj in invalid {
+            let sequential = parsing(obj, &ParseOptions::default()).unwrap_err();
+            for threads in 1..obj.len() {
+                assert_eq!(sequential, parsing_parallel(obj, &ParseOptions { threads, ..ParseOptions::default() }).unwrap_err());
+            }
+        }
+    }
+}
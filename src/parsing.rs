@@ -1,144 +1,986 @@
-fn max(a: f32, b:f32) -> f32 {
-    if a > b {
-        a
-    } else {
-        b
-    }
-}
-fn min(a: f32, b:f32) -> f32 {
-    if a < b {
-        a
-    } else {
-        b
-    }
-}
+use std::{collections::HashMap, error::Error, fmt, io::{self, BufRead}, thread};
+use crate::{bounds::{Aabb, BoundingSphere}, freeform::{self, Basis, Statement}, material::Material, normals::generate_normals, tangents::generate_tangents, tokenizer::{tokens, Continuation}, triangulate::triangulate, uv::{self, Projection}};
 
 #[derive(Copy, Clone, Debug)]
 pub struct Vertex {
     pub position: (f32, f32, f32),
-    tex_coords: [f32; 2],
+    pub tex_coords: [f32; 2],
+    pub color: [f32; 4],
 }
 
-implement_vertex!(Vertex, position, tex_coords);
+implement_vertex!(Vertex, position, tex_coords, color);
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug)]
 pub struct Normal {
-    normal: (f32, f32, f32)
+    pub normal: (f32, f32, f32)
 }
 
 implement_vertex!(Normal, normal);
 
+/// Direction u grows in along the surface, for normal maps. `w` is the sign of the
+/// bitangent, `cross(normal, tangent) * w`, the same as MikkTSpace.
+#[derive(Copy, Clone, Debug)]
+pub struct Tangent {
+    pub tangent: [f32; 4]
+}
+
+implement_vertex!(Tangent, tangent);
+
+
+/// Consecutive faces, lines and points sharing the same object, group and material,
+/// drawn from `indices[start..end]`, `lines[line_start..line_end]` and `points[point_start..point_end]`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Submesh {
+    pub object: Option<String>,
+    pub group: Option<String>,
+    pub material: Option<String>,
+    pub start: usize,
+    pub end: usize,
+    pub line_start: usize,
+    pub line_end: usize,
+    pub point_start: usize,
+    pub point_end: usize,
+    /// box around the submesh's faces, lines and points
+    pub bounds: Aabb,
+}
+
+impl Submesh {
+    /// `object/group`, as shown by the viewer
+    pub fn name(&self) -> String {
+        match (&self.object, &self.group) {
+            (Some(object), Some(group)) => format!("{object}/{group}"),
+            (Some(name), None) | (None, Some(name)) => name.clone(),
+            (None, None) => String::from("default"),
+        }
+    }
+}
+
+/// Values of a vertex property which isn't drawn, like the confidence of a scan,
+/// one per vertex
+#[derive(Clone, Debug, PartialEq)]
+pub struct Scalars {
+    pub name: String,
+    pub values: Vec<f32>,
+}
+
+#[derive(Debug)]
+pub struct Model {
+    pub vertices: Vec<Vertex>,
+    pub normals: Vec<Normal>,
+    /// one per vertex, made from the normals and texture coordinates
+    pub tangents: Vec<Tangent>,
+    pub indices: Vec<u32>,
+    /// pairs of indices, one per segment of the `l` polylines
+    pub lines: Vec<u32>,
+    /// indices of the `p` points
+    pub points: Vec<u32>,
+    /// box and sphere around all the vertices, the viewer turns the model around their center
+    pub bounds: Aabb,
+    pub sphere: BoundingSphere,
+    /// some vertices have a color, the others are white
+    pub has_colors: bool,
+    pub submeshes: Vec<Submesh>,
+    /// files named by `mtllib`, the loader fills `materials` from them
+    pub material_libs: Vec<String>,
+    pub materials: Vec<Material>,
+    /// shown in false colors by the viewer
+    pub scalars: Vec<Scalars>,
+    /// how the texture coordinates were made, None when the file has its own
+    pub uv: Option<Projection>,
+    /// vertices split by the projection or the tangents, they are copies of these ones
+    pub seams: Vec<u32>,
+    /// positions of the file no face, line, point or free-form element uses
+    pub unreferenced: Vec<[f32; 3]>,
+    pub warnings: Vec<Warning>,
+}
+
+impl Model {
+    /// Names of the submeshes' groups, in order of appearance and without duplicates
+    pub fn group_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = Vec::new();
+        for name in self.submeshes.iter().map(Submesh::name) {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        names
+    }
+}
+
+// statements of the obj format we know about but don't draw (yet)
+const IGNORED_DIRECTIVES: &[&str] = &[
+    "mg", "maplib", "usemap",
+    "bmat", "step", "con", "lod", "bevel", "c_interp", "d_interp", "ctech", "stech",
+    "shadow_obj", "trace_obj", "call", "csh",
+];
+
+/// Where a parsing error happened: 1-based line and column, the offending
+/// token and the whole line it was found on.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+    pub token: String,
+    pub text: String,
+}
+
+impl Location {
+    pub fn new(line: usize, text: &str, token: &str) -> Self {
+        // tokens are sub slices of the line, their offset is their column
+        let offset = token.as_ptr() as usize - text.as_ptr() as usize;
+        Location {
+            line,
+            column: text[..offset].chars().count() + 1,
+            token: token.to_string(),
+            text: text.trim_end().to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParseError {
+    /// A vertex, normal or texture component isn't a float
+    BadFloat(Location),
+    /// A statement doesn't have the number of values it needs
+    WrongArity { at: Location, expected: usize, found: usize },
+    /// A face indice or a smoothing group isn't an integer
+    BadIndex(Location),
+    /// A face indice points outside of the list it refers to
+    IndexOutOfRange { at: Location, len: usize },
+    /// A keyword the format doesn't have, where it can't be skipped
+    UnsupportedDirective(Location),
+    /// The input couldn't be read past this line
+    Io { line: usize, kind: io::ErrorKind, message: String },
+    /// A binary file is malformed at this byte offset
+    Binary { offset: usize, message: String },
+    /// The file doesn't follow its format, as told by a decoding library
+    Invalid(String),
+}
+
+impl ParseError {
+    pub fn location(&self) -> Option<&Location> {
+        match self {
+            ParseError::BadFloat(at)
+            | ParseError::WrongArity { at, .. }
+            | ParseError::BadIndex(at)
+            | ParseError::IndexOutOfRange { at, .. }
+            | ParseError::UnsupportedDirective(at) => Some(at),
+            ParseError::Io { .. } | ParseError::Binary { .. } | ParseError::Invalid(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let Some(at) = self.location() else {
+            return match self {
+                ParseError::Io { line, message, .. } => write!(f, "line {line}: {message}"),
+                ParseError::Binary { offset, message } => write!(f, "byte {offset}: {message}"),
+                ParseError::Invalid(message) => write!(f, "{message}"),
+                _ => Ok(()),
+            };
+        };
+        write!(f, "line {}, column {}: ", at.line, at.column)?;
+        match self {
+            ParseError::BadFloat(at) => writeln!(f, "Vertex/Normal/Texture value must be float, found {:?}", at.token)?,
+            ParseError::WrongArity { at, expected, found } => writeln!(f, "{:?} needs at least {expected} values, found {found}", at.token)?,
+            ParseError::BadIndex(at) => writeln!(f, "value must be an integer, found {:?}", at.token)?,
+            ParseError::IndexOutOfRange { at, len } => writeln!(f, "indice {:?} is out of range, only {len} elements are defined", at.token)?,
+            ParseError::UnsupportedDirective(at) => writeln!(f, "unsupported directive {:?}", at.token)?,
+            ParseError::Io { .. } | ParseError::Binary { .. } | ParseError::Invalid(_) => {},
+        };
+        // caret style excerpt of the faulty line
+        let gutter = at.line.to_string().len();
+        writeln!(f, "{:gutter$} |", "")?;
+        writeln!(f, "{} | {}", at.line, at.text.replace('\t', " "))?;
+        write!(f, "{:gutter$} | {:pad$}{}", "", "", "^".repeat(at.token.chars().count().max(1)), pad = at.column - 1)
+    }
+}
+
+impl Error for ParseError {}
+
+/// Something odd which doesn't prevent the object from being displayed
+#[derive(Clone, Debug, PartialEq)]
+pub struct Warning {
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {line}: {}", self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+// resolve a 1-based obj indice against a list of len elements,
+// negative indices are relative to the end of the list
+fn parse_index(token: &str, len: usize, at: &dyn Fn(&str) -> Location) -> Result<usize, ParseError> {
+    let indice = token.parse::<i64>().map_err(|_| ParseError::BadIndex(at(token)))?;
+    let resolved = if indice < 0 { len as i64 + indice } else { indice - 1 };
+    match resolved {
+        i if i < 0 || i >= len as i64 => Err(ParseError::IndexOutOfRange { at: at(token), len }),
+        i => Ok(i as usize),
+    }
+}
+
+// texture and normal indices of a face corner may be omitted (`1//3`, `1/2`, `1`)
+fn parse_optional_index(token: Option<&str>, len: usize, at: &dyn Fn(&str) -> Location) -> Result<Option<usize>, ParseError> {
+    match token {
+        Some(token) if !token.is_empty() => parse_index(token, len, at).map(Some),
+        _ => Ok(None),
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ParseOptions {
+    /// In degrees, faces further apart than this don't share generated normals
+    pub crease_angle: f32,
+    /// Above 1 the file is read in memory and parsed by that many threads
+    pub threads: usize,
+    /// Texture coordinates of the files without any
+    pub uv: Projection,
+    /// Segments in every knot span of the free-form curves and surfaces
+    pub resolution: usize,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions { crease_angle: 180.0, threads: 1, uv: Projection::Planar, resolution: 8 }
+    }
+}
+
+/// A face corner, as indices in the position/texture/normal lists of `ObjData`
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Corner {
+    pub position: usize,
+    pub texture: Option<usize>,
+    pub normal: Option<usize>,
+    /// in `ObjData::face_colors`, in place of the position's color
+    pub color: Option<usize>,
+}
+
+// a face waiting to be triangulated, its corners are `polygon_corners[start..end]`
+#[derive(Copy, Clone, Debug)]
+struct Polygon {
+    line: usize,
+    start: usize,
+    end: usize,
+    smoothing: u32,
+}
+
+/// How the next faces are drawn, as set by `o`, `g`, `usemtl` and `s`
+#[derive(Clone, Debug, PartialEq)]
+pub struct State {
+    pub object: Option<String>,
+    pub group: Option<String>,
+    pub material: Option<String>,
+    pub smoothing: u32,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        State { object: None, group: None, material: None, smoothing: 1 }
+    }
+}
+
+/// Everything read from the obj statements, before triangulation and welding.
+/// The other formats fill one too, to be built the same way.
+#[derive(Debug, Default)]
+pub struct ObjData {
+    pub positions: Vec<[f32; 3]>,
+    /// only filled up to the last vertex with a color
+    pub colors: Vec<[f32; 4]>,
+    /// `w` of the positions, only filled up to the last vertex with one
+    pub weights: Vec<f32>,
+    pub textures: Vec<[f32; 2]>,
+    pub normals: Vec<(f32, f32, f32)>,
+    /// colors given to faces rather than vertices
+    pub face_colors: Vec<[f32; 4]>,
+    polygons: Vec<Polygon>,
+    polygon_corners: Vec<Corner>,
+    // two corners per segment, one per point
+    line_corners: Vec<Corner>,
+    point_corners: Vec<Corner>,
+    // their start and end are polygon indices until the faces are triangulated
+    submeshes: Vec<Submesh>,
+    pub material_libs: Vec<String>,
+    /// one value per position
+    pub scalars: Vec<Scalars>,
+    /// curves and surfaces, tessellated by `build`
+    pub freeform: Vec<Statement>,
+    /// statements skipped while reading
+    pub warnings: Vec<Warning>,
+}
+
+impl ObjData {
+    // the submesh the next element of `state` goes in, a new one when the state changed
+    fn submesh(&mut self, state: &State) -> &mut Submesh {
+        let same = self.submeshes.last()
+            .is_some_and(|last| last.object == state.object && last.group == state.group && last.material == state.material);
+        if !same {
+            let (faces, lines, points) = (self.polygons.len(), self.line_corners.len(), self.point_corners.len());
+            self.submeshes.push(Submesh {
+                object: state.object.clone(),
+                group: state.group.clone(),
+                material: state.material.clone(),
+                start: faces,
+                end: faces,
+                line_start: lines,
+                line_end: lines,
+                point_start: points,
+                point_end: points,
+                bounds: Aabb::default(),
+            });
+        }
+        self.submeshes.last_mut().unwrap()
+    }
+
+    /// Add a face, drawn in the submesh of `state`. `line` is where it's declared, for warnings
+    pub fn add_polygon(&mut self, line: usize, corners: &[Corner], state: &State) {
+        let start = self.polygon_corners.len();
+        self.submesh(state).end += 1;
+        self.polygon_corners.extend_from_slice(corners);
+        self.polygons.push(Polygon { line, start, end: self.polygon_corners.len(), smoothing: state.smoothing });
+    }
+
+    /// Add the segments between consecutive corners, drawn in the submesh of `state`
+    pub fn add_polyline(&mut self, corners: &[Corner], state: &State) {
+        self.submesh(state).line_end += corners.len().saturating_sub(1) * 2;
+        self.line_corners.extend(corners.windows(2).flatten());
+    }
+
+    pub fn add_point(&mut self, corner: Corner, state: &State) {
+        self.submesh(state).point_end += 1;
+        self.point_corners.push(corner);
+    }
+
+    /// Add the statements which followed the ones of `self`
+    pub fn append(&mut self, other: ObjData) {
+        if !other.colors.is_empty() {
+            self.colors.resize(self.positions.len(), [1.0; 4]);
+            self.colors.extend(other.colors);
+        }
+        if !other.weights.is_empty() {
+            self.weights.resize(self.positions.len(), 1.0);
+            self.weights.extend(other.weights);
+        }
+        self.positions.extend(other.positions);
+        self.textures.extend(other.textures);
+        self.normals.extend(other.normals);
+        let (polygon_offset, corner_offset) = (self.polygons.len(), self.polygon_corners.len());
+        self.polygons.extend(other.polygons.into_iter().map(|p| Polygon {
+            start: p.start + corner_offset,
+            end: p.end + corner_offset,
+            ..p
+        }));
+        let (line_offset, point_offset) = (self.line_corners.len(), self.point_corners.len());
+        let color_offset = self.face_colors.len();
+        let shift = |corner: Corner| Corner { color: corner.color.map(|c| c + color_offset), ..corner };
+        self.face_colors.extend(other.face_colors);
+        self.polygon_corners.extend(other.polygon_corners.into_iter().map(shift));
+        self.line_corners.extend(other.line_corners.into_iter().map(shift));
+        self.point_corners.extend(other.point_corners.into_iter().map(shift));
+        for submesh in other.submeshes {
+            let submesh = Submesh {
+                start: submesh.start + polygon_offset,
+                end: submesh.end + polygon_offset,
+                line_start: submesh.line_start + line_offset,
+                line_end: submesh.line_end + line_offset,
+                point_start: submesh.point_start + point_offset,
+                point_end: submesh.point_end + point_offset,
+                ..submesh
+            };
+            match self.submeshes.last_mut() {
+                Some(last) if (&last.object, &last.group, &last.material) == (&submesh.object, &submesh.group, &submesh.material) => {
+                    (last.end, last.line_end, last.point_end) = (submesh.end, submesh.line_end, submesh.point_end)
+                },
+                _ => self.submeshes.push(submesh),
+            }
+        }
+        self.material_libs.extend(other.material_libs);
+        self.freeform.extend(other.freeform);
+        self.warnings.extend(other.warnings);
+    }
+}
+
+/// Reads obj statements one line at a time
+pub struct LineParser {
+    pub data: ObjData,
+    pub state: State,
+    // positions, textures and normals declared before the first line this parser
+    // reads, when the file is split between several parsers
+    base: [usize; 3],
+    // number of the last line read
+    line: usize,
+    continuation: Continuation,
+    element: Vec<Corner>,
+}
 
-pub fn parsing(obj: String) -> Result<(Vec<Vertex>, Vec<Normal>, Vec<u16>, [f32; 3]), String> {
-    let mut vertices = vec![Vertex {position: (0.0, 0.0, 0.0), tex_coords: [0.0, 0.0]}];
-    let mut textures = vec![Vertex {position: (0.0, 0.0, 0.0), tex_coords: [0.0, 0.0]}];
-    let mut normals = vec![Normal {normal: (0.0, 0.0, 0.0)}];
-    let mut indices = Vec::new();
-    let lines: Vec<&str> = obj.split('\n').collect();
-    for line in lines.iter() {
-        let chunk = line.split(' ').map(|ch| ch.trim()).filter(|ch| !ch.is_empty()).collect::<Vec<&str>>();
-        let mut chunk_iter = chunk.into_iter();
+impl LineParser {
+    /// `line` lines, declaring `base` positions/textures/normals, come before the first one it reads
+    pub fn new(base: [usize; 3], line: usize, state: State) -> Self {
+        LineParser { data: ObjData::default(), state, base, line, continuation: Continuation::default(), element: Vec::new() }
+    }
+
+    fn corner(&self, chunk: &str, at: &dyn Fn(&str) -> Location) -> Result<Corner, ParseError> {
+        let mut x = chunk.split('/');
+        Ok(Corner {
+            position: parse_index(x.next().unwrap_or(chunk), self.base[0] + self.data.positions.len(), at)?,
+            texture: parse_optional_index(x.next(), self.base[1] + self.data.textures.len(), at)?,
+            normal: parse_optional_index(x.next(), self.base[2] + self.data.normals.len(), at)?,
+            color: None,
+        })
+    }
+
+    /// Read the next line, a statement continued with `\` is read with its last line
+    pub fn parse_line(&mut self, line: &str) -> Result<(), ParseError> {
+        self.line += 1;
+        let mut continuation = std::mem::take(&mut self.continuation);
+        let result = match continuation.push(self.line, line) {
+            Some((nb, statement)) => self.parse_statement(nb, statement),
+            None => Ok(()),
+        };
+        self.continuation = continuation;
+        result
+    }
+
+    /// Read the statement the last line left unfinished, if any
+    pub fn finish(&mut self) -> Result<(), ParseError> {
+        let mut continuation = std::mem::take(&mut self.continuation);
+        let result = match continuation.finish() {
+            Some((nb, statement)) => self.parse_statement(nb, statement),
+            None => Ok(()),
+        };
+        self.continuation = continuation;
+        result
+    }
+
+    fn parse_statement(&mut self, nb: usize, line: &str) -> Result<(), ParseError> {
+        let at = |token: &str| Location::new(nb, line, token);
+        let mut chunk_iter = tokens(line);
+        let data = &mut self.data;
         match chunk_iter.next() {
             // vertices / normal and textures
-            v@(Some("v") | Some("vn") | Some("vt")) => {
-                let pos: Vec<f32> = chunk_iter.fold(Ok(Vec::new()), |acc: Result<Vec<f32>, String>, x| {
-                    match acc {
-                        Ok(mut acc) => {
-                            acc.push(x.parse::<f32>().map_err(|_| format!("Vertex/Normal/Texture value must be float {x:?}"))?);
-                            Ok(acc)
-                        },
-                        _ => acc
-                    }
-                })?;
-                if let Some("vt") = v {
-                    textures.push(Vertex {
-                        position: (0., 0., 0.),
-                        tex_coords: [
-                            *pos.get(0).ok_or(String::from("Your texture must be composed of 2 points"))?,
-                            *pos.get(1).ok_or(String::from("Your texture must be composed of 2 points"))?,
-                        ]
-                    });
-                    continue
+            Some(v@("v" | "vn" | "vt")) => {
+                let pos = chunk_iter
+                    .map(|x| x.parse::<f32>().map_err(|_| ParseError::BadFloat(at(x))))
+                    .collect::<Result<Vec<f32>, ParseError>>()?;
+                let expected = if v == "vt" { 2 } else { 3 };
+                if pos.len() < expected {
+                    return Err(ParseError::WrongArity { at: at(v), expected, found: pos.len() });
                 }
-                let pos = (
-                    *pos.get(0).ok_or(String::from("Your vertex/normal must be composed of 3 points"))?,
-                    *pos.get(1).ok_or(String::from("Your vertex/normal must be composed of 3 points"))?,
-                    *pos.get(2).ok_or(String::from("Your vertex/normal must be composed of 3 points"))?
-                );
-                if let Some("v") = v {
-                    vertices.push(Vertex {
-                        position: pos,
-                        tex_coords: [0.0, 0.0]
-                    });
-                } else {
-                    normals.push(Normal {
-                        normal: pos
-                    })
+                match v {
+                    "vt" => data.textures.push([pos[0], pos[1]]),
+                    "v" => {
+                        // `x y z [w]`, `x y z r g b` or `x y z r g b a`
+                        let color = match pos.len() {
+                            3 => None,
+                            4 => {
+                                // weight of the control points of rational curves and surfaces
+                                data.weights.resize(data.positions.len(), 1.0);
+                                data.weights.push(pos[3]);
+                                None
+                            },
+                            6 => Some([pos[3], pos[4], pos[5], 1.0]),
+                            5 => return Err(ParseError::WrongArity { at: at(v), expected: 6, found: 5 }),
+                            _ => Some([pos[3], pos[4], pos[5], pos[6]]),
+                        };
+                        if let Some(color) = color {
+                            data.colors.resize(data.positions.len(), [1.0; 4]);
+                            data.colors.push(color);
+                        }
+                        data.positions.push([pos[0], pos[1], pos[2]]);
+                    },
+                    _ => data.normals.push((pos[0], pos[1], pos[2])),
                 }
             },
             // faces
-            Some("f") => {
-                for (i, chunk) in chunk_iter.enumerate() {
-                    //TODO handle Vertice/Texture/Normal
-                    let x: Vec<&str> = chunk.split('/').collect();
-                    let indice = x.get(0).ok_or(String::from("No indice given"))?.parse::<u16>().map_err(|_| format!("indices value must be an u16 number {x:?}"))?;
-                    indices.push(indice);
-
-                    // apply texture to vertex
-                    if let Some(vertex) = vertices.get_mut(indice as usize) {
-                        if let Some(tex_indice_s) = x.get(1) {
-                            if let Some(tex) = textures.get(tex_indice_s.parse::<usize>().map_err(|_| format!("indices value must be an usize number {x:?}"))?) {
-                                vertex.tex_coords = tex.tex_coords;
-                            }
-                        }
-                    }
-
-                    if i == 3 {
-                        let x_3 = indices.pop().unwrap();
-                        let x_1 = indices[indices.len() - 1];
-                        let x_2 = indices[indices.len() - 3];
-                        indices.push(x_1);
-                        indices.push(x_2);
-                        indices.push(x_3);
-                    }
-
+            Some(f@"f") => {
+                let mut element = std::mem::take(&mut self.element);
+                element.clear();
+                for chunk in chunk_iter {
+                    element.push(self.corner(chunk, &at)?);
+                }
+                if element.len() < 3 {
+                    return Err(ParseError::WrongArity { at: at(f), expected: 3, found: element.len() });
                 }
+                self.data.add_polygon(nb, &element, &self.state);
+                self.element = element;
             },
+            // polylines and points
+            Some(e@("l" | "p")) => {
+                let mut element = std::mem::take(&mut self.element);
+                element.clear();
+                for chunk in chunk_iter {
+                    element.push(self.corner(chunk, &at)?);
+                }
+                let expected = if e == "l" { 2 } else { 1 };
+                if element.len() < expected {
+                    return Err(ParseError::WrongArity { at: at(e), expected, found: element.len() });
+                }
+                // `v/vt` like `l`, unlit so without normals
+                element.iter_mut().for_each(|corner| corner.normal = None);
+                match e {
+                    "l" => self.data.add_polyline(&element, &self.state),
+                    _ => element.iter().for_each(|&corner| self.data.add_point(corner, &self.state)),
+                }
+                self.element = element;
+            },
+            // a new object starts in the default group
+            Some("o") => {
+                self.state.object = Some(chunk_iter.collect::<Vec<&str>>().join(" ")).filter(|name| !name.is_empty());
+                self.state.group = None;
+            },
+            Some("g") => self.state.group = Some(chunk_iter.collect::<Vec<&str>>().join(" ")).filter(|name| !name.is_empty()),
+            // smoothing group, 0 or off is flat shading
+            Some(s@"s") => {
+                self.state.smoothing = match chunk_iter.next() {
+                    Some("off") => 0,
+                    Some(n) => n.parse::<u32>().map_err(|_| ParseError::BadIndex(at(n)))?,
+                    None => return Err(ParseError::WrongArity { at: at(s), expected: 1, found: 0 }),
+                }
+            },
+            // free-form geometry, put together by `freeform::tessellate`
+            Some(c@"cstype") => {
+                let words: Vec<&str> = chunk_iter.collect();
+                let (rational, name) = match words[..] {
+                    ["rat", name] => (true, name),
+                    [name] => (false, name),
+                    _ => return Err(ParseError::WrongArity { at: at(c), expected: 1, found: words.len() }),
+                };
+                data.freeform.push(Statement::Type { rational, basis: Basis::from_name(name) });
+            },
+            Some(d@"deg") => {
+                let degrees = chunk_iter
+                    .map(|x| x.parse::<usize>().map_err(|_| ParseError::BadIndex(at(x))))
+                    .collect::<Result<Vec<usize>, ParseError>>()?;
+                if degrees.is_empty() {
+                    return Err(ParseError::WrongArity { at: at(d), expected: 1, found: 0 });
+                }
+                data.freeform.push(Statement::Degree(degrees[0], degrees.get(1).copied()));
+            },
+            Some(e@("curv" | "surf")) => {
+                let tokens: Vec<&str> = chunk_iter.collect();
+                let floats = if e == "curv" { 2 } else { 4 };
+                if tokens.len() < floats + 2 {
+                    return Err(ParseError::WrongArity { at: at(e), expected: floats + 2, found: tokens.len() });
+                }
+                let range = tokens[..floats].iter()
+                    .map(|x| x.parse::<f32>().map_err(|_| ParseError::BadFloat(at(x))))
+                    .collect::<Result<Vec<f32>, ParseError>>()?;
+                // surfaces may give a texture and a normal to their control points, only the position is used
+                let points = tokens[floats..].iter()
+                    .map(|x| self.corner(x, &at).map(|corner| corner.position))
+                    .collect::<Result<Vec<usize>, ParseError>>()?;
+                self.data.freeform.push(match e {
+                    "curv" => Statement::Curve { line: nb, range: [range[0], range[1]], points, state: self.state.clone() },
+                    _ => Statement::Surface { line: nb, range: [[range[0], range[1]], [range[2], range[3]]], points, state: self.state.clone() },
+                });
+            },
+            Some(p@"parm") => {
+                let direction = match chunk_iter.next() {
+                    Some("u") => 0,
+                    Some("v") => 1,
+                    Some(direction) => return Err(ParseError::UnsupportedDirective(at(direction))),
+                    None => return Err(ParseError::WrongArity { at: at(p), expected: 2, found: 0 }),
+                };
+                let values = chunk_iter
+                    .map(|x| x.parse::<f32>().map_err(|_| ParseError::BadFloat(at(x))))
+                    .collect::<Result<Vec<f32>, ParseError>>()?;
+                data.freeform.push(Statement::Parameters { direction, values });
+            },
+            Some("end") => data.freeform.push(Statement::End(nb)),
+            // `vp u [v] [w]` and `curv2` are points and curves in parameter space, only the trimming curves use them
+            Some(v@"vp") => {
+                let values = chunk_iter
+                    .map(|x| x.parse::<f32>().map_err(|_| ParseError::BadFloat(at(x))))
+                    .collect::<Result<Vec<f32>, ParseError>>()?;
+                if values.is_empty() {
+                    return Err(ParseError::WrongArity { at: at(v), expected: 1, found: 0 });
+                }
+            },
+            Some("curv2") => {},
+            Some(directive@("trim" | "hole" | "scrv" | "sp")) => data.warnings.push(Warning {
+                line: Some(nb),
+                message: format!("{directive:?} isn't supported, the surface is drawn untrimmed"),
+            }),
+            Some(m@("mtllib" | "usemtl")) => {
+                let names: Vec<&str> = chunk_iter.collect();
+                match m {
+                    "mtllib" if names.is_empty() => return Err(ParseError::WrongArity { at: at(m), expected: 1, found: 0 }),
+                    "mtllib" => data.material_libs.extend(names.into_iter().map(String::from)),
+                    // a `usemtl` without a name goes back to no material
+                    _ => self.state.material = (!names.is_empty()).then(|| names.join(" ")),
+                }
+            },
+            // extensions of other exporters don't prevent the rest of the file from being read
+            Some(directive) if !IGNORED_DIRECTIVES.contains(&directive) => data.warnings.push(Warning {
+                line: Some(nb),
+                message: format!("unknown directive {directive:?}, skipped"),
+            }),
             _ => {}
         }
+        Ok(())
     }
+}
 
-    // define distance between origin and object's center
-    let mut iter = vertices.iter();
-    iter.next();
-    let mut center = [0., 0., 0.];
-    if let Some(fst) = iter.next() {
-        let image_bounds = iter.fold(
-            [fst.position.0, fst.position.1, fst.position.0, fst.position.1, fst.position.2, fst.position.2],
-            |acc, v| [min(acc[0], v.position.0), max(acc[1], v.position.1), max(acc[2], v.position.0), min(acc[3], v.position.1), min(acc[4], v.position.2), max(acc[5], v.position.2)]);
-        let size_y = image_bounds[1] - image_bounds[3];
-        let size_z = image_bounds[5] - image_bounds[4];
-        let size_x = image_bounds[2] - image_bounds[0];
-        center = [size_x / 2.0 + image_bounds[0], size_y / 2.0 + image_bounds[3], size_z / 2.0 + image_bounds[4]];
-
-        if textures.len() == 1 {
-            vertices.iter_mut().for_each(|v| {
-                let t_x = (v.position.0 + image_bounds[0].abs()) / (image_bounds[2] + image_bounds[0].abs());
-                let t_y = (v.position.1 + image_bounds[3].abs()) / (image_bounds[1] + image_bounds[3].abs());
-                let t_z = (v.position.2 + image_bounds[4].abs()) / (image_bounds[5] + image_bounds[4].abs());
-                v.tex_coords = match size_x > size_y {
-                    true => match size_z > size_y {
-                        true => [t_x, t_z],
-                        false => [t_x, t_y],
-                    },
-                    false => match size_z > size_x {
-                        true => [t_z, t_y],
-                        false => [t_z, t_y]
-                    }
-                };
+/// Parse an obj file line by line, the whole file is never held in memory
+pub fn parsing<R: BufRead>(mut reader: R, options: &ParseOptions) -> Result<Model, ParseError> {
+    let mut parser = LineParser::new([0; 3], 0, State::default());
+    // reused from one line to the next
+    let mut line = String::new();
+    loop {
+        line.clear();
+        match reader.read_line(&mut line) {
+            Ok(0) => break,
+            Ok(_) => parser.parse_line(&line)?,
+            Err(e) => return Err(ParseError::Io { line: parser.line + 1, kind: e.kind(), message: e.to_string() }),
+        }
+    }
+    parser.finish()?;
+    Ok(build(parser.data, options))
+}
+
+// triangles of a run of polygons
+#[derive(Default)]
+struct Triangulated {
+    // three per triangle
+    corners: Vec<Corner>,
+    // smoothing group of every triangle
+    smoothing: Vec<u32>,
+    // where the corners of each polygon start
+    starts: Vec<usize>,
+    warnings: Vec<Warning>,
+}
+
+fn triangulate_polygons(data: &ObjData, polygons: &[Polygon]) -> Triangulated {
+    let Triangulated { mut corners, mut smoothing, mut starts, mut warnings } = Triangulated::default();
+    let mut points: Vec<[f32; 3]> = Vec::new();
+    for polygon in polygons {
+        let polygon_corners = &data.polygon_corners[polygon.start..polygon.end];
+        points.clear();
+        points.extend(polygon_corners.iter().map(|c| data.positions[c.position]));
+        let (triangles, degeneracy) = triangulate(&points);
+        if let Some(degeneracy) = degeneracy {
+            warnings.push(Warning { line: Some(polygon.line), message: degeneracy.to_string() });
+        }
+        starts.push(corners.len());
+        smoothing.extend(triangles.iter().map(|_| polygon.smoothing));
+        corners.extend(triangles.into_iter().flatten().map(|i| polygon_corners[i]));
+    }
+    Triangulated { corners, smoothing, starts, warnings }
+}
+
+/// Triangulate, generate the missing normals and weld the corners into vertices
+pub fn build(mut data: ObjData, options: &ParseOptions) -> Model {
+    // control points are used even if they aren't drawn
+    let mut used = vec![false; data.positions.len()];
+    for statement in data.freeform.iter() {
+        if let Statement::Curve { points, .. } | Statement::Surface { points, .. } = statement {
+            points.iter().for_each(|&p| used[p] = true);
+        }
+    }
+    let freeform_warnings = freeform::tessellate(&mut data, options.resolution);
+    // polygons are split in as many runs as there are threads, each run keeps its order
+    let run = data.polygons.len().div_ceil(options.threads.max(1)).max(1);
+    let runs: Vec<Triangulated> = match options.threads > 1 {
+        true => thread::scope(|scope| {
+            let handles: Vec<_> = data.polygons.chunks(run)
+                .map(|polygons| scope.spawn(|| triangulate_polygons(&data, polygons)))
+                .collect();
+            handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+        }),
+        false => vec![triangulate_polygons(&data, &data.polygons)],
+    };
+    let mut corners: Vec<Corner> = Vec::new();
+    let mut smoothing: Vec<u32> = Vec::new();
+    let mut polygon_starts: Vec<usize> = Vec::with_capacity(data.polygons.len() + 1);
+    let mut warnings = std::mem::take(&mut data.warnings);
+    warnings.extend(freeform_warnings);
+    for run in runs {
+        polygon_starts.extend(run.starts.into_iter().map(|start| start + corners.len()));
+        corners.extend(run.corners);
+        smoothing.extend(run.smoothing);
+        warnings.extend(run.warnings);
+    }
+    polygon_starts.push(corners.len());
+    let ObjData { positions, colors, textures, normals, face_colors, line_corners, point_corners, mut submeshes, material_libs, scalars, .. } = data;
+    for submesh in submeshes.iter_mut() {
+        submesh.start = polygon_starts[submesh.start];
+        submesh.end = polygon_starts[submesh.end];
+    }
+
+    // corners without a normal get one from the faces around them
+    let generated = match corners.iter().any(|c| c.normal.is_none()) {
+        true => {
+            let triangles: Vec<[usize; 3]> = corners.chunks(3)
+                .map(|t| [t[0].position, t[1].position, t[2].position])
+                .collect();
+            generate_normals(&positions, &triangles, &smoothing, options.crease_angle)
+        },
+        false => Vec::new(),
+    };
+    // every distinct position/texture/normal triplet becomes one vertex
+    let mut welded: HashMap<(Corner, [u32; 3]), u32> = HashMap::new();
+    let mut vertices = Vec::new();
+    let mut vertex_normals = Vec::new();
+    // position each vertex was made from
+    let mut sources: Vec<usize> = Vec::new();
+    let mut weld = |corner: Corner, normal: (f32, f32, f32)| -> u32 {
+        let key = (corner, [normal.0.to_bits(), normal.1.to_bits(), normal.2.to_bits()]);
+        *welded.entry(key).or_insert_with(|| {
+            let [x, y, z] = positions[corner.position];
+            vertices.push(Vertex {
+                position: (x, y, z),
+                tex_coords: corner.texture.map_or([0.0, 0.0], |t| textures[t]),
+                color: match corner.color {
+                    Some(c) => face_colors[c],
+                    None => *colors.get(corner.position).unwrap_or(&[1.0; 4]),
+                },
             });
+            vertex_normals.push(Normal { normal });
+            sources.push(corner.position);
+            vertices.len() as u32 - 1
+        })
+    };
+    let indices: Vec<u32> = corners.iter()
+        .enumerate()
+        .map(|(i, corner)| match corner.normal {
+            Some(n) => weld(*corner, normals[n]),
+            None => weld(*corner, (generated[i][0], generated[i][1], generated[i][2])),
+        })
+        .collect();
+    // lines and points aren't lit, they don't need a normal
+    let lines: Vec<u32> = line_corners.into_iter().map(|corner| weld(corner, (0.0, 0.0, 0.0))).collect();
+    let points: Vec<u32> = point_corners.into_iter().map(|corner| weld(corner, (0.0, 0.0, 0.0))).collect();
+
+    used.resize(positions.len(), false);
+    sources.iter().for_each(|&p| used[p] = true);
+    let unreferenced: Vec<[f32; 3]> = positions.iter().zip(used).filter(|(_, used)| !used).map(|(p, _)| *p).collect();
+
+    let scalars: Vec<Scalars> = scalars.into_iter()
+        .map(|Scalars { name, values }| Scalars { name, values: sources.iter().map(|&p| values[p]).collect() })
+        .collect();
+
+    let position = |v: &Vertex| [v.position.0, v.position.1, v.position.2];
+    let bounds = Aabb::from_points(vertices.iter().map(position));
+    let sphere = BoundingSphere::from_points(&bounds, vertices.iter().map(position));
+
+    for submesh in submeshes.iter_mut() {
+        let elements = indices[submesh.start..submesh.end].iter()
+            .chain(lines[submesh.line_start..submesh.line_end].iter())
+            .chain(points[submesh.point_start..submesh.point_end].iter());
+        submesh.bounds = Aabb::from_points(elements.map(|&i| position(&vertices[i as usize])));
+    }
+
+    let mut model = Model {
+        vertices,
+        normals: vertex_normals,
+        tangents: Vec::new(),
+        indices,
+        lines,
+        points,
+        bounds,
+        sphere,
+        has_colors: !colors.is_empty() || !face_colors.is_empty(),
+        submeshes,
+        material_libs,
+        materials: Vec::new(),
+        scalars,
+        uv: None,
+        seams: Vec::new(),
+        unreferenced,
+        warnings,
+    };
+    // the projection makes the tangents along with the texture coordinates
+    match textures.is_empty() {
+        true => uv::project(&mut model, options.uv),
+        false => generate_tangents(&mut model),
+    }
+    model
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parsing, ParseError, ParseOptions, Warning};
+
+    fn error(obj: &str) -> ParseError {
+        parsing(obj.as_bytes(), &ParseOptions::default()).unwrap_err()
+    }
+
+    #[test]
+    fn error_location() {
+        let e = error("v 1 2 3\nv\t1 x 3\n");
+        let at = e.location().unwrap();
+        assert_eq!((at.line, at.column, at.token.as_str(), at.text.as_str()), (2, 5, "x", "v\t1 x 3"));
+        assert_eq!(e.to_string(), "line 2, column 5: Vertex/Normal/Texture value must be float, found \"x\"\n  |\n2 | v 1 x 3\n  |     ^");
+        // columns count characters, not bytes
+        let e = error("# é\nv é 2 3\n");
+        assert_eq!(e.location().unwrap().column, 3);
+        assert!(e.to_string().ends_with("2 | v é 2 3\n  |   ^"));
+    }
+
+    #[test]
+    fn error_caret_width() {
+        let mut obj = String::from("v 0 0 0\n").repeat(9);
+        obj.push_str("f 1 2 -10\n");
+        let e = error(&obj);
+        assert_eq!(e, ParseError::IndexOutOfRange { at: e.location().unwrap().clone(), len: 9 });
+        // the gutter is as wide as the line number, the caret as wide as the token
+        assert!(e.to_string().ends_with("\n   |\n10 | f 1 2 -10\n   |       ^^^"), "{e}");
+    }
+
+    #[test]
+    fn unknown_directives() {
+        let obj = "v 0 0 0\nv 1 0 0\nxyz_extension 1 2\nv 0 1 0\nf 1 2 3\n";
+        let model = parsing(obj.as_bytes(), &ParseOptions::default()).unwrap();
+        assert_eq!(model.indices.len(), 3);
+        assert_eq!(model.warnings, [Warning { line: Some(3), message: String::from("unknown directive \"xyz_extension\", skipped") }]);
+        // directives of the format which aren't drawn are skipped silently
+        let model = parsing("v 0 0 0\nlod 1\nbevel off\n".as_bytes(), &ParseOptions::default()).unwrap();
+        assert!(model.warnings.is_empty());
+        // trimming curves change what is drawn
+        let model = parsing("vp 0 0\nvp 1 0\ncurv2 1 2\ntrim 0 1 1\n".as_bytes(), &ParseOptions::default()).unwrap();
+        assert_eq!(model.warnings, [Warning { line: Some(4), message: String::from("\"trim\" isn't supported, the surface is drawn untrimmed") }]);
+    }
+
+    #[test]
+    fn more_than_65535_vertices() {
+        use std::fmt::Write;
+        // a strip of 35000 quads, 70002 vertices
+        let mut obj = String::new();
+        for i in 0..35001 {
+            writeln!(obj, "v {i} 0 0\nv {i} 1 0").unwrap();
+        }
+        for i in 0..35000 {
+            writeln!(obj, "f {} {} {} {}", 2 * i + 1, 2 * i + 3, 2 * i + 4, 2 * i + 2).unwrap();
         }
+        let model = parsing(obj.as_bytes(), &ParseOptions::default()).unwrap();
+        assert_eq!(model.vertices.len(), 70002);
+        assert_eq!(model.indices.len(), 35000 * 6);
+        let last = *model.indices.iter().max().unwrap();
+        assert!(last > u16::MAX as u32);
+        assert_eq!(model.vertices[last as usize].position.0, 35000.0);
+    }
 
+    fn positions(obj: &str) -> Vec<(f32, f32, f32)> {
+        let model = parsing(obj.as_bytes(), &ParseOptions::default()).unwrap();
+        model.indices.iter().map(|&i| model.vertices[i as usize].position).collect()
+    }
+
+    #[test]
+    fn negative_indices() {
+        // relative to the elements declared so far, not to the whole file
+        let obj = "v 0 0 0\nv 1 0 0\nv 0 1 0\nf -3 -2 -1\nv 5 5 5\nv 6 5 5\nf -5 -2 -1\nv 7 7 7\n";
+        assert_eq!(positions(obj), [(0.0, 0.0, 0.0), (1.0, 0.0, 0.0), (0.0, 1.0, 0.0), (0.0, 0.0, 0.0), (5.0, 5.0, 5.0), (6.0, 5.0, 5.0)]);
+        let obj = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0.5 0.5\nvt 1 1\nvn 0 0 -1\nvn 0 0 1\nf -3/-1/-1 -2/-2/-2 -1/-1/-1\n";
+        let model = parsing(obj.as_bytes(), &ParseOptions::default()).unwrap();
+        let corners: Vec<([f32; 2], (f32, f32, f32))> = model.indices.iter()
+            .map(|&i| (model.vertices[i as usize].tex_coords, model.normals[i as usize].normal))
+            .collect();
+        assert_eq!(corners, [([1.0, 1.0], (0.0, 0.0, 1.0)), ([0.5, 0.5], (0.0, 0.0, -1.0)), ([1.0, 1.0], (0.0, 0.0, 1.0))]);
+    }
+
+    #[test]
+    fn out_of_range_indices() {
+        let triangle = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\n";
+        for (face, token, len) in [("f 1 2 4", "4", 3), ("f 0 1 2", "0", 3), ("f -4 1 2", "-4", 3), ("f 1/2 2/1 3/1", "2", 1), ("f 1//1 2 3", "1", 0)] {
+            match error(&format!("{triangle}{face}\n")) {
+                ParseError::IndexOutOfRange { at, len: found } => {
+                    assert_eq!((at.line, at.token.as_str(), found), (5, token, len), "{face}");
+                },
+                e => panic!("{face}: unexpected {e:?}"),
+            }
+        }
+        assert!(matches!(error(&format!("{triangle}f 1 x 2\n")), ParseError::BadIndex(at) if at.token == "x"));
+    }
+
+    #[test]
+    fn welding() {
+        let quad = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvt 0 0\nvt 1 1\nvn 0 0 1\nvn 0 1 0\n";
+        let count = |faces: &str| {
+            let model = parsing(format!("{quad}{faces}").as_bytes(), &ParseOptions::default()).unwrap();
+            (model.vertices.len(), model.indices)
+        };
+        // the corners the two triangles share are the same vertices
+        let (vertices, indices) = count("f 1/1/1 2/1/1 3/1/1\nf 1/1/1 3/1/1 4/1/1\n");
+        assert_eq!((vertices, indices), (4, vec![0, 1, 2, 0, 2, 3]));
+        // another texture coordinate, another normal or none of them make another vertex
+        assert_eq!(count("f 1/1/1 2/1/1 3/1/1\nf 1/2/1 3/1/1 4/1/1\n").0, 5);
+        assert_eq!(count("f 1/1/1 2/1/1 3/1/1\nf 1/1/2 3/1/1 4/1/1\n").0, 5);
+        assert_eq!(count("f 1/1/1 2/1/1 3/1/1\nf 1//1 3//1 4//1\n").0, 6);
+        assert_eq!(count("f 1 2 3\nf 1 3 4\n").0, 4);
+        // same values declared twice are still other elements of the file
+        assert_eq!(count("vt 0 0\nf 1/1/1 2/1/1 3/1/1\nf 1/3/1 3/1/1 4/1/1\n").0, 5);
+    }
+
+    #[test]
+    fn submeshes() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3\n\
+            o chair\nf 1 2 3 4\ng leg\nf 1 2 3\nusemtl wood\nf 1 2 3\ng leg\ns 2\nf 1 2 3\n\
+            g seat cushion\nusemtl\tfabric\nf 1 2 3\no table\nf 1 2 3\nusemtl wood\nv 5 5 5\nf 1 2 3\n";
+        let model = parsing(obj.as_bytes(), &ParseOptions::default()).unwrap();
+        let submeshes: Vec<_> = model.submeshes.iter()
+            .map(|s| (s.object.as_deref(), s.group.as_deref(), s.material.as_deref(), s.start, s.end))
+            .collect();
+        // a statement which doesn't change the state doesn't start another submesh, `o` resets the group
+        assert_eq!(submeshes, [
+            (None, None, None, 0, 3),
+            (Some("chair"), None, None, 3, 9),
+            (Some("chair"), Some("leg"), None, 9, 12),
+            (Some("chair"), Some("leg"), Some("wood"), 12, 18),
+            (Some("chair"), Some("seat cushion"), Some("fabric"), 18, 21),
+            (Some("table"), None, Some("fabric"), 21, 24),
+            (Some("table"), None, Some("wood"), 24, 27),
+        ]);
+        assert_eq!(model.group_names(), ["default", "chair", "chair/leg", "chair/seat cushion", "table"]);
+        // the bounds of a submesh only hold its own faces
+        assert_eq!(model.submeshes[1].bounds.max, [1.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn lines_and_points_in_submeshes() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 0 1 0\nv 5 5 5\nf 1 2 3\nl 1 2 3\ng wire\nl 1 4\np 1 2\ng dots\np 4\ng wire\nf 1 2 3\np 3\n";
+        let model = parsing(obj.as_bytes(), &ParseOptions::default()).unwrap();
+        let submeshes: Vec<_> = model.submeshes.iter()
+            .map(|s| (s.name(), s.start..s.end, s.line_start..s.line_end, s.point_start..s.point_end))
+            .collect();
+        assert_eq!(submeshes, [
+            (String::from("default"), 0..3, 0..4, 0..0),
+            (String::from("wire"), 3..3, 4..6, 0..2),
+            (String::from("dots"), 3..3, 6..6, 2..3),
+            (String::from("wire"), 3..6, 6..6, 3..4),
+        ]);
+        assert_eq!(model.group_names(), ["default", "wire", "dots"]);
+        // a submesh of lines or points only has bounds too
+        assert_eq!((model.submeshes[1].bounds.min, model.submeshes[1].bounds.max), ([0.0; 3], [5.0; 3]));
+        assert_eq!(model.submeshes[2].bounds.min, [5.0; 3]);
+        for threads in 2..8 {
+            let parallel = crate::parallel::parsing_parallel(obj.as_bytes(), &ParseOptions { threads, ..ParseOptions::default() }).unwrap();
+            assert_eq!(model.submeshes, parallel.submeshes);
+        }
+    }
+
+    #[test]
+    fn vertex_colors() {
+        let colors = |obj: &str| {
+            let model = parsing(obj.as_bytes(), &ParseOptions::default()).unwrap();
+            (model.has_colors, model.indices.iter().map(|&i| model.vertices[i as usize].color).collect::<Vec<[f32; 4]>>())
+        };
+        assert_eq!(colors("v 0 0 0 1 0 0\nv 1 0 0 0 1 0 0.5\nv 0 1 0\nf 1 2 3\n"), (true, vec![[1.0, 0.0, 0.0, 1.0], [0.0, 1.0, 0.0, 0.5], [1.0; 4]]));
+        // vertices without a color before the first one with a color are white
+        assert_eq!(colors("v 0 0 0\nv 1 0 0\nv 0 1 0 0 0 1\nf 1 2 3\n"), (true, vec![[1.0; 4], [1.0; 4], [0.0, 0.0, 1.0, 1.0]]));
+        // a fourth value is a weight, not a color
+        assert_eq!(colors("v 0 0 0 2\nv 1 0 0\nv 0 1 0\nf 1 2 3\n"), (false, vec![[1.0; 4]; 3]));
+        match error("v 0 0 0 1 1\n") {
+            ParseError::WrongArity { at, expected, found } => assert_eq!((at.token.as_str(), expected, found), ("v", 6, 5)),
+            e => panic!("unexpected {e:?}"),
+        }
+        // the same position with other colors makes other vertices
+        let model = parsing("v 0 0 0 1 0 0\nv 0 0 0 0 1 0\nv 1 0 0\nv 0 1 0\nf 1 3 4\nf 2 3 4\n".as_bytes(), &ParseOptions::default()).unwrap();
+        assert_eq!(model.vertices.len(), 4);
     }
-    Ok((vertices, normals, indices, center))
 }
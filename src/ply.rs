@@ -0,0 +1,515 @@
+use std::str::SplitWhitespace;
+use crate::parsing::{build, Corner, Location, Model, ObjData, ParseError, ParseOptions, Scalars, State};
+
+// types of the values, as named in the header
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn from_name(name: &str) -> Option<Scalar> {
+        Some(match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+
+    // colors stored as integers go from 0 to the type's maximum
+    fn color(self, value: f64) -> f32 {
+        match self {
+            Scalar::U8 => value as f32 / 255.0,
+            Scalar::U16 => value as f32 / 65535.0,
+            _ => value as f32,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Property {
+    Scalar(String, Scalar),
+    // type of the count, then of the items
+    List(String, Scalar, Scalar),
+}
+
+#[derive(Debug)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+impl Element {
+    // the fewest bytes an instance takes, ascii ones are at least a character
+    fn min_size(&self, encoding: Encoding) -> usize {
+        match encoding {
+            Encoding::Ascii => 1,
+            _ => self.properties.iter()
+                .map(|property| match property {
+                    Property::Scalar(_, scalar) | Property::List(_, scalar, _) => scalar.size(),
+                })
+                .sum(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Encoding {
+    Ascii,
+    LittleEndian,
+    BigEndian,
+}
+
+// the values following the header, one element instance after the other
+struct Body<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    encoding: Encoding,
+    // ascii lines are numbered from the start of the file
+    header_lines: usize,
+    // ascii files have one instance per line
+    lines: std::iter::Enumerate<std::str::SplitInclusive<'a, char>>,
+    line: (usize, &'a str),
+    tokens: SplitWhitespace<'a>,
+    read: usize,
+    last: &'a str,
+}
+
+impl<'a> Body<'a> {
+    fn at(&self, token: &str) -> Location {
+        Location::new(self.header_lines + self.line.0 + 1, self.line.1, token)
+    }
+
+    // bytes not read yet
+    fn left(&self) -> usize {
+        match self.encoding {
+            Encoding::Ascii => self.lines.clone().map(|(_, line)| line.len()).sum(),
+            _ => self.bytes.len() - self.offset,
+        }
+    }
+
+    fn start_instance(&mut self) -> Result<(), ParseError> {
+        if self.encoding != Encoding::Ascii {
+            return Ok(());
+        }
+        loop {
+            let Some((nb, line)) = self.lines.next() else {
+                return Err(ParseError::Binary { offset: self.bytes.len(), message: String::from("the file ends before its last element") });
+            };
+            if !line.trim().is_empty() {
+                self.line = (nb, line);
+                self.tokens = line.split_whitespace();
+                self.read = 0;
+                return Ok(());
+            }
+        }
+    }
+
+    fn value(&mut self, scalar: Scalar) -> Result<f64, ParseError> {
+        if self.encoding == Encoding::Ascii {
+            let Some(token) = self.tokens.next() else {
+                let line = self.line.1.trim();
+                return Err(ParseError::WrongArity { at: self.at(line), expected: self.read + 1, found: self.read });
+            };
+            self.read += 1;
+            self.last = token;
+            return token.parse::<f64>().map_err(|_| ParseError::BadFloat(self.at(token)));
+        }
+        let Some(bytes) = self.bytes.get(self.offset..self.offset + scalar.size()) else {
+            return Err(ParseError::Binary { offset: self.bytes.len(), message: String::from("the file ends before its last element") });
+        };
+        self.offset += scalar.size();
+        let mut buffer = [0u8; 8];
+        buffer[..bytes.len()].copy_from_slice(bytes);
+        if self.encoding == Encoding::BigEndian {
+            buffer[..bytes.len()].reverse();
+        }
+        let [b0, b1, b2, b3, ..] = buffer;
+        Ok(match scalar {
+            Scalar::I8 => b0 as i8 as f64,
+            Scalar::U8 => b0 as f64,
+            Scalar::I16 => i16::from_le_bytes([b0, b1]) as f64,
+            Scalar::U16 => u16::from_le_bytes([b0, b1]) as f64,
+            Scalar::I32 => i32::from_le_bytes([b0, b1, b2, b3]) as f64,
+            Scalar::U32 => u32::from_le_bytes([b0, b1, b2, b3]) as f64,
+            Scalar::F32 => f32::from_le_bytes([b0, b1, b2, b3]) as f64,
+            Scalar::F64 => f64::from_le_bytes(buffer),
+        })
+    }
+
+    fn index(&mut self, scalar: Scalar, len: usize) -> Result<usize, ParseError> {
+        let offset = self.offset;
+        let index = self.value(scalar)?;
+        match index >= 0.0 && (index as usize) < len && index.fract() == 0.0 {
+            true => Ok(index as usize),
+            false if self.encoding == Encoding::Ascii => Err(ParseError::IndexOutOfRange { at: self.at(self.last), len }),
+            false => Err(ParseError::Binary { offset, message: format!("vertex index {index} is out of range, only {len} vertices are defined") }),
+        }
+    }
+
+    // where an instance was read, in place of its line for binary files
+    fn line(&self, instance: usize) -> usize {
+        match self.encoding {
+            Encoding::Ascii => self.header_lines + self.line.0 + 1,
+            _ => instance + 1,
+        }
+    }
+}
+
+struct Header {
+    encoding: Encoding,
+    elements: Vec<Element>,
+    // byte where the values start
+    end: usize,
+}
+
+fn parse_header(ply: &[u8]) -> Result<Header, ParseError> {
+    let Some(end) = ply.windows(11).position(|w| w == b"end_header\n" || w == b"end_header\r").map(|i| i + 11) else {
+        return Err(ParseError::Binary { offset: ply.len(), message: String::from("no end_header line") });
+    };
+    // a \r\n after end_header
+    let end = match ply.get(end - 1..end + 1) {
+        Some(b"\r\n") => end + 1,
+        _ => end,
+    };
+    let text = std::str::from_utf8(&ply[..end]).map_err(|e| ParseError::Binary {
+        offset: e.valid_up_to(),
+        message: String::from("the header isn't valid UTF-8"),
+    })?;
+    let mut encoding = None;
+    let mut elements: Vec<Element> = Vec::new();
+    for (nb, line) in text.split_inclusive('\n').enumerate() {
+        let at = |token: &str| Location::new(nb + 1, line, token);
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let arity = |expected: usize| match tokens.len() > expected {
+            true => Ok(()),
+            false => Err(ParseError::WrongArity { at: at(tokens[0]), expected, found: tokens.len() - 1 }),
+        };
+        let scalar = |name: &str| Scalar::from_name(name).ok_or_else(|| ParseError::UnsupportedDirective(at(name)));
+        match tokens.first() {
+            Some(&"ply") if nb == 0 => {},
+            _ if nb == 0 => return Err(ParseError::UnsupportedDirective(at(line.trim()))),
+            Some(&"format") => {
+                arity(2)?;
+                encoding = Some(match tokens[1] {
+                    "ascii" => Encoding::Ascii,
+                    "binary_little_endian" => Encoding::LittleEndian,
+                    "binary_big_endian" => Encoding::BigEndian,
+                    format => return Err(ParseError::UnsupportedDirective(at(format))),
+                });
+            },
+            Some(&"element") => {
+                arity(2)?;
+                elements.push(Element {
+                    name: tokens[1].to_string(),
+                    count: tokens[2].parse::<usize>().map_err(|_| ParseError::BadIndex(at(tokens[2])))?,
+                    properties: Vec::new(),
+                });
+            },
+            Some(&"property") => {
+                let Some(element) = elements.last_mut() else {
+                    return Err(ParseError::UnsupportedDirective(at(tokens[0])));
+                };
+                arity(2)?;
+                element.properties.push(match tokens[1] {
+                    "list" => {
+                        arity(4)?;
+                        Property::List(tokens[4].to_string(), scalar(tokens[2])?, scalar(tokens[3])?)
+                    },
+                    name => Property::Scalar(tokens[2].to_string(), scalar(name)?),
+                });
+            },
+            Some(&("comment" | "obj_info" | "end_header")) | None => {},
+            Some(directive) => return Err(ParseError::UnsupportedDirective(at(directive))),
+        }
+    }
+    let encoding = encoding.ok_or_else(|| ParseError::Binary { offset: end, message: String::from("no format line") })?;
+    Ok(Header { encoding, elements, end })
+}
+
+// where the standard vertex properties go, the others are kept as scalars
+enum Target {
+    Position(usize),
+    Normal(usize),
+    Color(usize),
+    Texture(usize),
+    Scalar(usize),
+}
+
+fn target(name: &str) -> Option<Target> {
+    Some(match name {
+        "x" => Target::Position(0),
+        "y" => Target::Position(1),
+        "z" => Target::Position(2),
+        "nx" => Target::Normal(0),
+        "ny" => Target::Normal(1),
+        "nz" => Target::Normal(2),
+        "red" | "diffuse_red" | "r" => Target::Color(0),
+        "green" | "diffuse_green" | "g" => Target::Color(1),
+        "blue" | "diffuse_blue" | "b" => Target::Color(2),
+        "alpha" | "a" => Target::Color(3),
+        "s" | "u" | "texture_u" | "texture_s" => Target::Texture(0),
+        "t" | "v" | "texture_v" | "texture_t" => Target::Texture(1),
+        _ => return None,
+    })
+}
+
+/// Parse an ascii or binary PLY file.
+/// Positions, normals, colors and texture coordinates go where the obj ones do,
+/// the other vertex properties are kept in `Model::scalars`.
+/// Without faces, every vertex is drawn as a point.
+pub fn parsing_ply(ply: &[u8], options: &ParseOptions) -> Result<Model, ParseError> {
+    let header = parse_header(ply)?;
+    let text = match header.encoding {
+        Encoding::Ascii => std::str::from_utf8(&ply[header.end..]).map_err(|e| ParseError::Binary {
+            offset: header.end + e.valid_up_to(),
+            message: String::from("stream did not contain valid UTF-8"),
+        })?,
+        _ => "",
+    };
+    let mut body = Body {
+        bytes: ply,
+        offset: header.end,
+        encoding: header.encoding,
+        header_lines: ply[..header.end].iter().filter(|&&b| b == b'\n').count(),
+        lines: text.split_inclusive('\n').enumerate(),
+        line: (0, ""),
+        tokens: "".split_whitespace(),
+        read: 0,
+        last: "",
+    };
+    let vertex = header.elements.iter().find(|e| e.name == "vertex");
+    let vertex_count = vertex.map_or(0, |e| e.count);
+    // from the header, faces may come before the vertices
+    let vertex_targets: Vec<Target> = vertex.map_or(Vec::new(), |e| e.properties.iter()
+        .filter_map(|property| match property {
+            Property::Scalar(name, _) => target(name),
+            Property::List(..) => None,
+        })
+        .collect());
+    let has_normals = vertex_targets.iter().any(|t| matches!(t, Target::Normal(_)));
+    let has_textures = vertex_targets.iter().any(|t| matches!(t, Target::Texture(_)));
+    let mut data = ObjData::default();
+    let state = State::default();
+    let mut has_faces = false;
+    let mut corners: Vec<Corner> = Vec::new();
+    for element in header.elements.iter() {
+        let targets: Vec<Option<Target>> = element.properties.iter()
+            .map(|property| match property {
+                Property::Scalar(name, _) if element.name == "vertex" => match target(name) {
+                    Some(target) => Some(target),
+                    None => {
+                        data.scalars.push(Scalars { name: name.clone(), values: Vec::new() });
+                        Some(Target::Scalar(data.scalars.len() - 1))
+                    },
+                },
+                _ => None,
+            })
+            .collect();
+        // the count comes from the header, check it against what is left before reading
+        let left = body.left();
+        if element.count.saturating_mul(element.min_size(header.encoding)) > left {
+            return Err(ParseError::Binary {
+                offset: ply.len(),
+                message: format!("{} {} elements do not fit in the {left} bytes left", element.count, element.name),
+            });
+        }
+        for instance in 0..element.count {
+            body.start_instance()?;
+            let (mut position, mut normal, mut color, mut texture) = ([0.0; 3], [0.0; 3], [1.0; 4], [0.0; 2]);
+            let mut has_color = false;
+            for (property, target) in element.properties.iter().zip(targets.iter()) {
+                match property {
+                    Property::Scalar(_, scalar) => {
+                        let value = body.value(*scalar)?;
+                        match target {
+                            Some(Target::Position(i)) => position[*i] = value as f32,
+                            Some(Target::Normal(i)) => normal[*i] = value as f32,
+                            Some(Target::Color(i)) => {
+                                color[*i] = scalar.color(value);
+                                has_color = true;
+                            },
+                            Some(Target::Texture(i)) => texture[*i] = value as f32,
+                            Some(Target::Scalar(i)) => data.scalars[*i].values.push(value as f32),
+                            None => {},
+                        }
+                    },
+                    Property::List(name, count, item) => {
+                        let count = body.value(*count)?;
+                        let is_face = element.name == "face" && (name == "vertex_indices" || name == "vertex_index");
+                        corners.clear();
+                        for _ in 0..count.max(0.0) as usize {
+                            match is_face {
+                                true => {
+                                    let position = body.index(*item, vertex_count)?;
+                                    corners.push(Corner {
+                                        position,
+                                        texture: has_textures.then_some(position),
+                                        normal: has_normals.then_some(position),
+                                        color: None,
+                                    });
+                                },
+                                false => {
+                                    body.value(*item)?;
+                                },
+                            }
+                        }
+                        if !is_face {
+                            continue
+                        }
+                        if corners.len() < 3 {
+                            let found = corners.len();
+                            return Err(match body.encoding {
+                                Encoding::Ascii => ParseError::WrongArity { at: body.at(body.line.1.trim()), expected: 3, found },
+                                _ => ParseError::Binary { offset: body.offset, message: format!("face {} has {found} vertices, 3 are needed", instance + 1) },
+                            });
+                        }
+                        has_faces = true;
+                        data.add_polygon(body.line(instance), &corners, &state);
+                    },
+                }
+            }
+            if element.name == "vertex" {
+                if has_color {
+                    data.colors.resize(data.positions.len(), [1.0; 4]);
+                    data.colors.push(color);
+                }
+                data.positions.push(position);
+                if has_normals {
+                    data.normals.push((normal[0], normal[1], normal[2]));
+                }
+                if has_textures {
+                    data.textures.push(texture);
+                }
+            }
+        }
+    }
+    // a point cloud
+    if !has_faces {
+        for position in 0..data.positions.len() {
+            data.add_point(Corner { position, texture: has_textures.then_some(position), normal: None, color: None }, &State::default());
+        }
+    }
+    Ok(build(data, options))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parsing_ply;
+    use crate::parsing::{ParseError, ParseOptions};
+
+    const HEADER: &str = "element vertex 4\nproperty float x\nproperty float y\nproperty float z\n\
+        property float nx\nproperty float ny\nproperty float nz\nproperty float u\nproperty float v\n\
+        property uchar red\nproperty uchar green\nproperty uchar blue\nproperty float confidence\n\
+        element face 1\nproperty list uchar int vertex_indices\nend_header\n";
+
+    // a square facing z, with texture coordinates, colors and a confidence
+    const VERTICES: [[f32; 9]; 4] = [
+        [0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.5],
+        [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.25],
+        [1.0, 1.0, 0.0, 0.0, 0.0, 1.0, 1.0, 1.0, 1.0],
+        [0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 1.0, 0.0],
+    ];
+    const COLORS: [[u8; 3]; 4] = [[255, 0, 0], [0, 255, 0], [0, 0, 255], [255, 255, 255]];
+
+    // the square as little or big endian bytes
+    fn binary(big_endian: bool) -> Vec<u8> {
+        let format = if big_endian { "binary_big_endian" } else { "binary_little_endian" };
+        let mut ply = format!("ply\nformat {format} 1.0\n{HEADER}").into_bytes();
+        let f32 = |x: f32| if big_endian { x.to_be_bytes() } else { x.to_le_bytes() };
+        let i32 = |x: i32| if big_endian { x.to_be_bytes() } else { x.to_le_bytes() };
+        for (vertex, color) in VERTICES.iter().zip(COLORS) {
+            ply.extend(vertex[..8].iter().flat_map(|&x| f32(x)));
+            ply.extend(color);
+            ply.extend(f32(vertex[8]));
+        }
+        ply.push(4);
+        ply.extend([0, 1, 2, 3].into_iter().flat_map(i32));
+        ply
+    }
+
+    fn ascii() -> String {
+        let mut ply = format!("ply\nformat ascii 1.0\ncomment a square\n{HEADER}");
+        for (vertex, [r, g, b]) in VERTICES.iter().zip(COLORS) {
+            let values: Vec<String> = vertex[..8].iter().map(f32::to_string).collect();
+            ply.push_str(&format!("{} {r} {g} {b} {}\n", values.join(" "), vertex[8]));
+        }
+        ply + "4 0 1 2 3\n"
+    }
+
+    #[test]
+    fn encodings() {
+        let bodies = [ascii().into_bytes(), binary(false), binary(true)];
+        for ply in bodies.iter() {
+            let model = parsing_ply(ply, &ParseOptions::default()).unwrap();
+            assert_eq!(model.indices, [0, 1, 2, 0, 2, 3]);
+            for (vertex, expected) in model.vertices.iter().zip(VERTICES) {
+                assert_eq!(vertex.position, (expected[0], expected[1], expected[2]));
+                assert_eq!(vertex.tex_coords, [expected[6], expected[7]]);
+            }
+            assert!(model.normals.iter().all(|n| n.normal == (0.0, 0.0, 1.0)));
+            assert_eq!(model.vertices[1].color, [0.0, 1.0, 0.0, 1.0]);
+            assert_eq!(model.scalars.len(), 1);
+            assert_eq!(model.scalars[0].name, "confidence");
+            assert_eq!(model.scalars[0].values, [0.5, 0.25, 1.0, 0.0]);
+        }
+    }
+
+    #[test]
+    fn faces_before_vertices() {
+        let ply = "ply\nformat ascii 1.0\nelement face 1\nproperty list uchar int vertex_indices\n\
+            element vertex 3\nproperty float x\nproperty float y\nproperty float z\nproperty float s\nproperty float t\nend_header\n\
+            3 0 1 2\n0 0 0 0 0\n1 0 0 1 0\n0 1 0 0 1\n";
+        let model = parsing_ply(ply.as_bytes(), &ParseOptions::default()).unwrap();
+        assert_eq!(model.indices, [0, 1, 2]);
+        // the file's own texture coordinates, not generated ones
+        assert_eq!(model.uv, None);
+        assert_eq!(model.vertices[2].tex_coords, [0.0, 1.0]);
+    }
+
+    #[test]
+    fn truncated() {
+        let ply = binary(true);
+        assert!(matches!(parsing_ply(&ply[..ply.len() - 1], &ParseOptions::default()), Err(ParseError::Binary { .. })));
+        let ply = ascii();
+        match parsing_ply(&ply.as_bytes()[..ply.len() - 3], &ParseOptions::default()) {
+            Err(ParseError::WrongArity { at, expected, found }) => assert_eq!((at.line, expected, found), (24, 5, 4)),
+            result => panic!("unexpected {result:?}"),
+        }
+    }
+
+    #[test]
+    fn oversized_count() {
+        for format in ["ascii", "binary_little_endian"] {
+            let ply = format!("ply\nformat {format} 1.0\nelement vertex 99999999999\nproperty float x\nproperty float y\n\
+                property float z\nproperty float confidence\nend_header\n0 0 0 1\n");
+            match parsing_ply(ply.as_bytes(), &ParseOptions::default()) {
+                Err(ParseError::Binary { message, .. }) => assert!(message.starts_with("99999999999 vertex elements")),
+                result => panic!("unexpected {result:?}"),
+            }
+        }
+    }
+}
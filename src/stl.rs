@@ -0,0 +1,206 @@
+use std::{collections::HashMap, io};
+use crate::parsing::{build, Corner, Location, Model, ObjData, ParseError, ParseOptions, State};
+
+// -0.0 and 0.0 are the same coordinate but not the same bits
+fn key(v: [f32; 3]) -> [u32; 3] {
+    v.map(|x| (x + 0.0).to_bits())
+}
+
+// STL repeats every position once per facet, they are welded back as they come
+struct Welder {
+    data: ObjData,
+    positions: HashMap<[u32; 3], usize>,
+    normals: HashMap<[u32; 3], usize>,
+}
+
+impl Welder {
+    fn new() -> Self {
+        Welder { data: ObjData::default(), positions: HashMap::new(), normals: HashMap::new() }
+    }
+
+    fn position(&mut self, p: [f32; 3]) -> usize {
+        let positions = &mut self.data.positions;
+        *self.positions.entry(key(p)).or_insert_with(|| {
+            positions.push(p);
+            positions.len() - 1
+        })
+    }
+
+    // facets written without a normal (all zeros) get theirs from their vertices
+    fn normal(&mut self, n: [f32; 3]) -> Option<usize> {
+        if n == [0.0; 3] || n.iter().any(|x| !x.is_finite()) {
+            return None;
+        }
+        let normals = &mut self.data.normals;
+        Some(*self.normals.entry(key(n)).or_insert_with(|| {
+            normals.push((n[0], n[1], n[2]));
+            normals.len() - 1
+        }))
+    }
+
+    fn facet(&mut self, line: usize, positions: &[[f32; 3]], normal: [f32; 3], state: &State) {
+        let normal = self.normal(normal);
+        let corners: Vec<Corner> = positions.iter()
+            .map(|&p| Corner { position: self.position(p), texture: None, normal, color: None })
+            .collect();
+        self.data.add_polygon(line, &corners, state);
+    }
+}
+
+// binary files may start with "solid" too, and their size often doesn't match
+// the facet count: a text file has no control bytes on its first line, and it goes
+// on with a facet or the end of the solid
+fn is_binary(stl: &[u8]) -> bool {
+    if !stl.starts_with(b"solid") {
+        return true;
+    }
+    let end = stl.iter().position(|&b| b == b'\n').unwrap_or(stl.len());
+    let next = stl.get(end + 1..).unwrap_or_default().trim_ascii_start();
+    stl[..end].iter().any(|&b| b.is_ascii_control() && b != b'\t' && b != b'\r')
+        || !(next.is_empty() || next.starts_with(b"facet") || next.starts_with(b"endsolid"))
+}
+
+/// Parse an ASCII or binary STL file.
+/// Facets are flat, their stored normal is used when there's one.
+pub fn parsing_stl(stl: &[u8], options: &ParseOptions) -> Result<Model, ParseError> {
+    let data = match is_binary(stl) {
+        true => parse_binary(stl)?,
+        false => parse_ascii(stl)?,
+    };
+    Ok(build(data, options))
+}
+
+fn parse_binary(stl: &[u8]) -> Result<ObjData, ParseError> {
+    let Some(count) = stl.get(80..84) else {
+        return Err(ParseError::Binary { offset: stl.len(), message: String::from("the 84 bytes header is truncated") });
+    };
+    let count = u32::from_le_bytes([count[0], count[1], count[2], count[3]]) as usize;
+    // flat shading, no smoothing across facets
+    let state = State { smoothing: 0, ..State::default() };
+    if stl.len() < 84 + 50 * count {
+        return Err(ParseError::Binary { offset: stl.len(), message: format!("{count} facets announced, the file ends before") });
+    }
+    let mut welder = Welder::new();
+    for (i, facet) in stl[84..].chunks_exact(50).take(count).enumerate() {
+        // normal, three vertices then an attribute byte count
+        let floats: Vec<f32> = facet[..48].chunks(4)
+            .map(|x| f32::from_le_bytes([x[0], x[1], x[2], x[3]]))
+            .collect();
+        let positions = [
+            [floats[3], floats[4], floats[5]],
+            [floats[6], floats[7], floats[8]],
+            [floats[9], floats[10], floats[11]],
+        ];
+        // binary facets are numbered in place of lines
+        welder.facet(i + 1, &positions, [floats[0], floats[1], floats[2]], &state);
+    }
+    Ok(welder.data)
+}
+
+fn parse_ascii(stl: &[u8]) -> Result<ObjData, ParseError> {
+    let text = std::str::from_utf8(stl).map_err(|e| ParseError::Io {
+        line: stl[..e.valid_up_to()].iter().filter(|&&b| b == b'\n').count() + 1,
+        kind: io::ErrorKind::InvalidData,
+        message: String::from("stream did not contain valid UTF-8"),
+    })?;
+    let mut state = State { smoothing: 0, ..State::default() };
+    let mut welder = Welder::new();
+    let mut normal = [0.0; 3];
+    let mut positions: Vec<[f32; 3]> = Vec::new();
+    for (nb, line) in text.split_inclusive('\n').enumerate() {
+        let at = |token: &str| Location::new(nb + 1, line, token);
+        let mut tokens = line.split_whitespace();
+        let floats = |keyword: &str, tokens: std::str::SplitWhitespace| -> Result<[f32; 3], ParseError> {
+            let values = tokens
+                .map(|x| x.parse::<f32>().map_err(|_| ParseError::BadFloat(at(x))))
+                .collect::<Result<Vec<f32>, ParseError>>()?;
+            match values.len() {
+                3 => Ok([values[0], values[1], values[2]]),
+                found => Err(ParseError::WrongArity { at: at(keyword), expected: 3, found }),
+            }
+        };
+        match tokens.next() {
+            // every solid of the file is an object
+            Some("solid") => state.object = Some(tokens.collect::<Vec<&str>>().join(" ")).filter(|name| !name.is_empty()),
+            Some(facet@"facet") => {
+                match tokens.next() {
+                    Some("normal") => normal = floats(facet, tokens)?,
+                    Some(token) => return Err(ParseError::UnsupportedDirective(at(token))),
+                    None => return Err(ParseError::WrongArity { at: at(facet), expected: 3, found: 0 }),
+                }
+                positions.clear();
+            },
+            Some(vertex@"vertex") => positions.push(floats(vertex, tokens)?),
+            Some(end@"endfacet") => {
+                if positions.len() < 3 {
+                    return Err(ParseError::WrongArity { at: at(end), expected: 3, found: positions.len() });
+                }
+                welder.facet(nb + 1, &positions, normal, &state);
+            },
+            Some("outer" | "endloop" | "endsolid") | None => {},
+            Some(directive) => return Err(ParseError::UnsupportedDirective(at(directive))),
+        }
+    }
+    Ok(welder.data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parsing_stl;
+    use crate::parsing::{ParseError, ParseOptions};
+
+    // a binary file of the triangles, with `header` and `trailer`
+    fn binary(header: &[u8], triangles: &[[[f32; 3]; 3]], trailer: &[u8]) -> Vec<u8> {
+        let mut stl = header.to_vec();
+        stl.resize(80, b' ');
+        stl.extend((triangles.len() as u32).to_le_bytes());
+        for triangle in triangles {
+            stl.extend([0.0f32; 3].iter().chain(triangle.iter().flatten()).flat_map(|x| x.to_le_bytes()));
+            stl.extend([0, 0]);
+        }
+        stl.extend(trailer);
+        stl
+    }
+
+    const SQUARE: [[[f32; 3]; 3]; 2] = [
+        [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0]],
+        [[-0.0, 0.0, -0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]],
+    ];
+
+    #[test]
+    fn welding() {
+        // the signed zeros are the same corner
+        let model = parsing_stl(&binary(b"", &SQUARE, b""), &ParseOptions::default()).unwrap();
+        assert_eq!(model.vertices.len(), 4);
+        assert_eq!(model.indices, [0, 1, 2, 0, 2, 3]);
+        let ascii = "solid square\nfacet normal 0 0 1\nouter loop\nvertex 0 0 0\nvertex 1 0 0\nvertex 1 1 0\nendloop\nendfacet\n\
+            facet normal 0 0 1\nouter loop\nvertex -0 0 -0\nvertex 1 1 0\nvertex 0 1 0\nendloop\nendfacet\nendsolid square\n";
+        let model = parsing_stl(ascii.as_bytes(), &ParseOptions::default()).unwrap();
+        assert_eq!(model.vertices.len(), 4);
+        assert_eq!(model.submeshes[0].object.as_deref(), Some("square"));
+    }
+
+    #[test]
+    fn binary_starting_with_solid() {
+        // written by tools that fill the header with the solid's name, with bytes after the facets
+        for trailer in [&b""[..], b"\0", b"padding\n"] {
+            let model = parsing_stl(&binary(b"solid square exported", &SQUARE, trailer), &ParseOptions::default()).unwrap();
+            assert_eq!(model.indices.len(), 6);
+        }
+        let model = parsing_stl(&binary(b"solid square\nexported", &SQUARE, b"\0\0"), &ParseOptions::default()).unwrap();
+        assert_eq!(model.indices.len(), 6);
+        // an empty text solid stays text
+        let model = parsing_stl(b"solid empty\nendsolid empty\n", &ParseOptions::default()).unwrap();
+        assert!(model.indices.is_empty());
+    }
+
+    #[test]
+    fn truncated() {
+        let stl = binary(b"", &SQUARE, b"");
+        match parsing_stl(&stl[..stl.len() - 10], &ParseOptions::default()) {
+            Err(ParseError::Binary { offset, .. }) => assert_eq!(offset, stl.len() - 10),
+            result => panic!("unexpected {result:?}"),
+        }
+        assert!(matches!(parsing_stl(&stl[..50], &ParseOptions::default()), Err(ParseError::Binary { .. })));
+    }
+}
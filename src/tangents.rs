@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+use crate::{
+    parsing::{Model, Tangent, Vertex},
+    vector::{dot, length, normalize, sub},
+};
+
+fn position(vertex: &Vertex) -> [f32; 3] {
+    [vertex.position.0, vertex.position.1, vertex.position.2]
+}
+
+// `v` without its part along the unit vector `n`
+fn flatten(v: [f32; 3], n: [f32; 3]) -> [f32; 3] {
+    let d = dot(v, n);
+    sub(v, [n[0] * d, n[1] * d, n[2] * d])
+}
+
+// any unit vector perpendicular to `n`, for vertices without texture directions
+fn perpendicular(n: [f32; 3]) -> [f32; 3] {
+    let axis = if n[0].abs() < 0.9 { [1.0, 0.0, 0.0] } else { [0.0, 1.0, 0.0] };
+    match length(flatten(axis, n)) > 0.0 {
+        true => normalize(flatten(axis, n)),
+        false => axis,
+    }
+}
+
+// the direction u grows in on the triangle, unit length and signed like MikkTSpace:
+// on faces whose texture is mirrored it points the other way and the flag is false
+fn face_tangent(p: [[f32; 3]; 3], uv: [[f32; 2]; 3]) -> Option<([f32; 3], bool)> {
+    let (d1, d2) = (sub(p[1], p[0]), sub(p[2], p[0]));
+    let (t21, t31) = ([uv[1][0] - uv[0][0], uv[1][1] - uv[0][1]], [uv[2][0] - uv[0][0], uv[2][1] - uv[0][1]]);
+    let area = t21[0] * t31[1] - t21[1] * t31[0];
+    let tangent = [0, 1, 2].map(|c| t31[1] * d1[c] - t21[1] * d2[c]);
+    let preserving = area > 0.0;
+    match area != 0.0 && length(tangent) > 0.0 {
+        true => Some((normalize(if preserving { tangent } else { tangent.map(|x| -x) }), preserving)),
+        false => None,
+    }
+}
+
+/// Tangent of every vertex, in the way of MikkTSpace: the tangents of the triangles
+/// around the vertex are made perpendicular to its normal and averaged, each weighted
+/// by the triangle's angle at the vertex in the tangent plane. `w` is the sign of the
+/// bitangent, `cross(normal, tangent) * w`, -1 where the texture is mirrored.
+/// Like MikkTSpace, a vertex shared by mirrored and unmirrored triangles is split: the
+/// mirrored ones get a copy of it, appended to the vertices and to `model.seams`.
+pub fn generate_tangents(model: &mut Model) {
+    let Model { vertices, normals, indices, scalars, seams, .. } = model;
+    let faces: Vec<Option<([f32; 3], bool)>> = indices.chunks_exact(3)
+        .map(|triangle| {
+            let corners = [0, 1, 2].map(|k| &vertices[triangle[k] as usize]);
+            face_tangent(corners.map(position), corners.map(|vertex| vertex.tex_coords))
+        })
+        .collect();
+
+    // the copies keep pointing at the vertices of the file, for the next projection
+    let count = vertices.len() - seams.len();
+    let mut preserving = vec![false; vertices.len()];
+    for (triangle, _) in indices.chunks_exact(3).zip(faces.iter()).filter(|(_, face)| matches!(face, Some((_, true)))) {
+        triangle.iter().for_each(|&i| preserving[i as usize] = true);
+    }
+    let mut copies: HashMap<u32, u32> = HashMap::new();
+    for (triangle, _) in indices.chunks_exact_mut(3).zip(faces.iter()).filter(|(_, face)| matches!(face, Some((_, false)))) {
+        for i in triangle.iter_mut().filter(|i| preserving[**i as usize]) {
+            let source = *i as usize;
+            *i = *copies.entry(*i).or_insert_with(|| {
+                vertices.push(vertices[source]);
+                normals.push(normals[source]);
+                for scalars in scalars.iter_mut() {
+                    scalars.values.push(scalars.values[source]);
+                }
+                seams.push(if source < count { source as u32 } else { seams[source - count] });
+                vertices.len() as u32 - 1
+            });
+        }
+    }
+
+    let normal = |i: usize| {
+        let (x, y, z) = normals[i].normal;
+        if length([x, y, z]) > 0.0 { normalize([x, y, z]) } else { [0.0, 0.0, 0.0] }
+    };
+    // sums of the tangents of the triangles around every vertex, now all mirrored or all not
+    let mut sums = vec![([0.0f32; 3], 1.0f32); vertices.len()];
+    for (triangle, face) in indices.chunks_exact(3).zip(faces) {
+        let Some((tangent, preserving)) = face else {
+            continue
+        };
+        let corners = [0, 1, 2].map(|k| triangle[k] as usize);
+        let p = corners.map(|i| position(&vertices[i]));
+        for k in 0..3 {
+            let n = normal(corners[k]);
+            let projected = flatten(tangent, n);
+            if length(projected) == 0.0 {
+                continue
+            }
+            let a = flatten(sub(p[(k + 1) % 3], p[k]), n);
+            let b = flatten(sub(p[(k + 2) % 3], p[k]), n);
+            let angle = match length(a) > 0.0 && length(b) > 0.0 {
+                true => dot(normalize(a), normalize(b)).clamp(-1.0, 1.0).acos(),
+                false => 0.0,
+            };
+            let (sum, sign) = &mut sums[corners[k]];
+            let projected = normalize(projected);
+            for c in 0..3 {
+                sum[c] += projected[c] * angle;
+            }
+            *sign = if preserving { 1.0 } else { -1.0 };
+        }
+    }
+    model.tangents = sums.into_iter()
+        .enumerate()
+        .map(|(i, (sum, sign))| {
+            let n = normal(i);
+            let sum = flatten(sum, n);
+            let [x, y, z] = if length(sum) > 0.0 { normalize(sum) } else { perpendicular(n) };
+            Tangent { tangent: [x, y, z, sign] }
+        })
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parsing::{parsing, Model, ParseOptions};
+
+    fn model(obj: &str) -> Model {
+        parsing(obj.as_bytes(), &ParseOptions::default()).unwrap()
+    }
+
+    fn assert_tangent(model: &Model, i: u32, expected: [f32; 4]) {
+        let found = model.tangents[i as usize].tangent;
+        assert!((0..4).all(|c| (found[c] - expected[c]).abs() < 1e-5), "vertex {i}: {found:?} instead of {expected:?}");
+    }
+
+    // a unit square facing z, with u given at its left and right sides
+    fn square(u: [f32; 2]) -> Model {
+        model(&format!("v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvt {0} 0\nvt {1} 0\nvt {1} 1\nvt {0} 1\nvn 0 0 1\n\
+            f 1/1/1 2/2/1 3/3/1 4/4/1\n", u[0], u[1]))
+    }
+
+    #[test]
+    fn quad() {
+        let model = square([0.0, 1.0]);
+        assert_eq!(model.vertices.len(), 4);
+        for i in 0..4 {
+            assert_tangent(&model, i, [1.0, 0.0, 0.0, 1.0]);
+        }
+    }
+
+    #[test]
+    fn mirrored_quad() {
+        // u grows towards -x, the bitangent still goes up v
+        let model = square([1.0, 0.0]);
+        assert_eq!(model.vertices.len(), 4);
+        for i in 0..4 {
+            assert_tangent(&model, i, [-1.0, 0.0, 0.0, -1.0]);
+        }
+    }
+
+    #[test]
+    fn mirror_seam() {
+        // two squares sharing the edge at x = 0, the texture mirrored on the left one
+        let model = model("v -1 0 0\nv 0 0 0\nv 1 0 0\nv -1 1 0\nv 0 1 0\nv 1 1 0\n\
+            vt 1 0\nvt 0 0\nvt 1 1\nvt 0 1\nvn 0 0 1\n\
+            f 1/1/1 2/2/1 5/4/1 4/3/1\nf 2/2/1 3/1/1 6/3/1 5/4/1\n");
+        // the two vertices of the shared edge are split
+        assert_eq!(model.vertices.len(), 8);
+        assert_eq!(model.seams.len(), 2);
+        for (copy, &source) in model.seams.iter().enumerate() {
+            assert_eq!(model.vertices[6 + copy].position, model.vertices[source as usize].position);
+        }
+        for triangle in model.indices.chunks_exact(3) {
+            let left = triangle.iter().map(|&i| model.vertices[i as usize].position.0).sum::<f32>() < 0.0;
+            let expected = if left { [-1.0, 0.0, 0.0, -1.0] } else { [1.0, 0.0, 0.0, 1.0] };
+            for &i in triangle {
+                assert_tangent(&model, i, expected);
+            }
+        }
+    }
+}
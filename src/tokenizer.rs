@@ -0,0 +1,139 @@
+// what's left of an obj line without its comment and its end
+fn content(line: &str) -> &str {
+    line.split('#').next().unwrap_or(line)
+}
+
+/// Tokens of an obj statement: separated by any whitespace, tabs and `\r` included,
+/// up to a `#` comment. The byte order mark some editors start the file with is
+/// skipped like a space.
+pub fn tokens(statement: &str) -> impl Iterator<Item = &str> {
+    content(statement)
+        .split(|c: char| c.is_whitespace() || c == '\u{feff}')
+        .filter(|token| !token.is_empty())
+}
+
+/// The statement goes on on the next line, its last character before the comment is `\`
+pub fn is_continued(line: &str) -> bool {
+    content(line).trim_end().ends_with('\\')
+}
+
+/// Puts back together the statements written on several lines
+#[derive(Default)]
+pub struct Continuation {
+    text: String,
+    // line the statement in `text` starts on
+    line: usize,
+    // `text` was handed out, the next line starts a new statement
+    done: bool,
+}
+
+impl Continuation {
+    /// Add the line numbered `nb`. Returns the statement it ends and the line it starts on,
+    /// or None when the statement goes on.
+    pub fn push<'a>(&'a mut self, nb: usize, line: &'a str) -> Option<(usize, &'a str)> {
+        if self.done {
+            self.text.clear();
+            self.done = false;
+        }
+        if is_continued(line) {
+            if self.text.is_empty() {
+                self.line = nb;
+            }
+            let content = content(line).trim_end();
+            self.text.push_str(&content[..content.len() - 1]);
+            self.text.push(' ');
+            return None;
+        }
+        if self.text.is_empty() {
+            return Some((nb, line));
+        }
+        self.text.push_str(line);
+        self.done = true;
+        Some((self.line, &self.text))
+    }
+
+    /// The statement the file ends in the middle of, if any
+    pub fn finish(&mut self) -> Option<(usize, &str)> {
+        match self.done || self.text.is_empty() {
+            true => None,
+            false => {
+                self.done = true;
+                Some((self.line, &self.text))
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parsing::{parsing, ParseError, ParseOptions};
+    use crate::parallel::parsing_parallel;
+    use super::{tokens, Continuation};
+
+    fn statements(lines: &[&str]) -> Vec<(usize, String)> {
+        let mut continuation = Continuation::default();
+        let mut statements = Vec::new();
+        for (i, line) in lines.iter().enumerate() {
+            if let Some((nb, statement)) = continuation.push(i + 1, line) {
+                statements.push((nb, statement.to_string()));
+            }
+        }
+        if let Some((nb, statement)) = continuation.finish() {
+            statements.push((nb, statement.to_string()));
+        }
+        statements
+    }
+
+    #[test]
+    fn whitespace_and_comments() {
+        assert_eq!(tokens("v\t1.0  2.0\t\t3.0\r\n").collect::<Vec<&str>>(), ["v", "1.0", "2.0", "3.0"]);
+        assert_eq!(tokens("f 1/1/1 2/2/2 3/3/3 # quad split by hand\n").collect::<Vec<&str>>(), ["f", "1/1/1", "2/2/2", "3/3/3"]);
+        assert_eq!(tokens("\u{feff}# Blender v2.79 OBJ File\n").count(), 0);
+        assert_eq!(tokens("\u{feff}mtllib model.mtl").collect::<Vec<&str>>(), ["mtllib", "model.mtl"]);
+        assert_eq!(tokens("   \t\r\n").count(), 0);
+    }
+
+    #[test]
+    fn continuations() {
+        let lines = ["f 1 2 \\\r\n", "  3 4\r\n", "v 0 0 0 # no continuation \\\n", "vt 1 \\\n", "1 \\\n"];
+        assert_eq!(statements(&lines), [
+            (1, String::from("f 1 2    3 4\r\n")),
+            (3, String::from("v 0 0 0 # no continuation \\\n")),
+            (4, String::from("vt 1  1  ")),
+        ]);
+    }
+
+    // an exporter writing CRLF, tabs, a BOM, trailing comments and long faces on several lines
+    const OBJ: &str = "\u{feff}# exported\r\nmtllib scene.mtl # library\r\no\tCube\r\nv\t-1 -1 0\r\nv 1 -1 0 # corner\r\nv 1 1 0\r\nv -1 1 0\r\n\
+        vt 0 0\r\nvt 1 0\r\nvt 1 1\r\nvt 0 1\r\nvn 0 0 1\r\nusemtl\tred\r\ns off\r\nf 1/1/1 2/2/1 \\\r\n  3/3/1 \\\r\n\t4/4/1 # quad\r\nl 1 \\\n2\n";
+
+    #[test]
+    fn exporter_files() {
+        let model = parsing(OBJ.as_bytes(), &ParseOptions::default()).unwrap();
+        assert_eq!(model.indices.len(), 6);
+        assert_eq!(model.lines.len(), 2);
+        assert_eq!(model.material_libs, ["scene.mtl"]);
+        assert_eq!(model.submeshes[0].object.as_deref(), Some("Cube"));
+        assert_eq!(model.submeshes[0].material.as_deref(), Some("red"));
+        for threads in 2..6 {
+            let parallel = parsing_parallel(OBJ.as_bytes(), &ParseOptions { threads, ..ParseOptions::default() }).unwrap();
+            assert_eq!(format!("{:?}", model.vertices), format!("{:?}", parallel.vertices));
+            assert_eq!(model.indices, parallel.indices);
+            assert_eq!(model.lines, parallel.lines);
+        }
+    }
+
+    // errors point at the first line of a continued statement
+    #[test]
+    fn continued_errors() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 \\\n  2 \\\n  4\n";
+        match parsing(obj.as_bytes(), &ParseOptions::default()) {
+            Err(ParseError::IndexOutOfRange { at, len }) => {
+                assert_eq!((at.line, at.token.as_str(), len), (4, "4", 3));
+            },
+            result => panic!("unexpected {result:?}"),
+        }
+        let parallel = parsing_parallel(obj.as_bytes(), &ParseOptions { threads: 4, ..ParseOptions::default() });
+        assert_eq!(parsing(obj.as_bytes(), &ParseOptions::default()).unwrap_err(), parallel.unwrap_err());
+    }
+}
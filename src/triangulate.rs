@@ -0,0 +1,161 @@
+use std::fmt;
+use crate::vector::{cross, dot, length, normalize, sub};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Degeneracy {
+    /// All the points are on the same line or on the same spot
+    ZeroArea,
+    /// No ear could be found, the polygon probably crosses itself
+    SelfIntersecting,
+}
+
+impl fmt::Display for Degeneracy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Degeneracy::ZeroArea => write!(f, "polygon has no area"),
+            Degeneracy::SelfIntersecting => write!(f, "polygon intersects itself"),
+        }
+    }
+}
+
+fn cross_2d(o: [f32; 2], a: [f32; 2], b: [f32; 2]) -> f32 {
+    (a[0] - o[0]) * (b[1] - o[1]) - (a[1] - o[1]) * (b[0] - o[0])
+}
+
+// inclusive test, a point on an edge of the triangle is inside
+fn in_triangle(p: [f32; 2], a: [f32; 2], b: [f32; 2], c: [f32; 2]) -> bool {
+    cross_2d(a, b, p) >= 0.0 && cross_2d(b, c, p) >= 0.0 && cross_2d(c, a, p) >= 0.0
+}
+
+/// Newell's normal of a polygon, its length is twice the polygon's area
+pub fn polygon_normal(polygon: &[[f32; 3]]) -> [f32; 3] {
+    let mut normal = [0.0, 0.0, 0.0];
+    for (i, p) in polygon.iter().enumerate() {
+        let n = polygon[(i + 1) % polygon.len()];
+        normal[0] += (p[1] - n[1]) * (p[2] + n[2]);
+        normal[1] += (p[2] - n[2]) * (p[0] + n[0]);
+        normal[2] += (p[0] - n[0]) * (p[1] + n[1]);
+    }
+    normal
+}
+
+fn fan(from: &[usize]) -> Vec<[usize; 3]> {
+    (1..from.len() - 1).map(|i| [from[0], from[i], from[i + 1]]).collect()
+}
+
+/// Split a polygon into triangles by ear clipping, the returned triangles index
+/// `polygon` and keep its winding.
+/// The polygon is projected on its best fit plane so it only needs to be roughly planar.
+/// Degenerate polygons are still split as a fan so nothing disappears.
+pub fn triangulate(polygon: &[[f32; 3]]) -> (Vec<[usize; 3]>, Option<Degeneracy>) {
+    let all: Vec<usize> = (0..polygon.len()).collect();
+    let normal = polygon_normal(polygon);
+    let extent = polygon.iter().skip(1).map(|p| length(sub(*p, polygon[0]))).fold(0.0, f32::max);
+    if length(normal) <= f32::EPSILON * extent * extent {
+        return (fan(&all), Some(Degeneracy::ZeroArea));
+    }
+    if polygon.len() == 3 {
+        return (fan(&all), None);
+    }
+
+    // (u, v, normal) is direct so the polygon turns counter clockwise in the plane
+    let normal = normalize(normal);
+    let helper = if normal[0].abs() < 0.9 { [1.0, 0.0, 0.0] } else { [0.0, 1.0, 0.0] };
+    let u = normalize(cross(helper, normal));
+    let v = cross(normal, u);
+    let points: Vec<[f32; 2]> = polygon.iter().map(|p| [dot(*p, u), dot(*p, v)]).collect();
+    let tolerance = f32::EPSILON * extent * extent;
+
+    let mut triangles = Vec::with_capacity(polygon.len() - 2);
+    let mut remaining = all;
+    while remaining.len() > 3 {
+        let len = remaining.len();
+        let ear = (1..=len).find_map(|i| {
+            let (a, b, c) = (remaining[i - 1], remaining[i % len], remaining[(i + 1) % len]);
+            let area = cross_2d(points[a], points[b], points[c]);
+            if area.abs() <= tolerance {
+                // collinear points are dropped without making a flat triangle
+                return Some((i % len, None));
+            }
+            if area < 0.0 {
+                return None;
+            }
+            let blocked = remaining.iter()
+                .filter(|&&p| p != a && p != b && p != c)
+                .filter(|&&p| points[p] != points[a] && points[p] != points[b] && points[p] != points[c])
+                .any(|&p| in_triangle(points[p], points[a], points[b], points[c]));
+            match blocked {
+                true => None,
+                false => Some((i % len, Some([a, b, c]))),
+            }
+        });
+        match ear {
+            Some((i, triangle)) => {
+                triangles.extend(triangle);
+                remaining.remove(i);
+            },
+            None => {
+                triangles.extend(fan(&remaining));
+                return (triangles, Some(Degeneracy::SelfIntersecting));
+            }
+        }
+    }
+    if cross_2d(points[remaining[0]], points[remaining[1]], points[remaining[2]]).abs() > tolerance {
+        triangles.push([remaining[0], remaining[1], remaining[2]]);
+    }
+    (triangles, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{polygon_normal, triangulate, Degeneracy};
+    use crate::vector::length;
+
+    fn area(polygon: &[[f32; 3]], triangles: &[[usize; 3]]) -> f32 {
+        triangles.iter().map(|t| length(polygon_normal(&t.map(|i| polygon[i]))) / 2.0).sum()
+    }
+
+    #[test]
+    fn concave_polygon() {
+        // an arrow pointing up, its notch at (2, 1) is the only reflex corner
+        let arrow = [[0.0, 0.0, 0.0], [2.0, 1.0, 0.0], [4.0, 0.0, 0.0], [2.0, 4.0, 0.0]];
+        let (triangles, degeneracy) = triangulate(&arrow);
+        assert_eq!(degeneracy, None);
+        assert_eq!(triangles.len(), 2);
+        assert!((area(&arrow, &triangles) - 6.0).abs() < 1e-5);
+        // the triangles keep the winding of the polygon, the fan from 0 would go outside
+        for t in triangles.iter() {
+            assert!(polygon_normal(&t.map(|i| arrow[i]))[2] > 0.0, "{triangles:?}");
+        }
+        assert!(!triangles.contains(&[0, 1, 2]) && !triangles.contains(&[2, 3, 0]));
+
+        // a comb in the yz plane, wound clockwise seen from +x
+        let comb: Vec<[f32; 3]> = [[0.0, 0.0], [0.0, 3.0], [1.0, 3.0], [1.0, 1.0], [2.0, 1.0], [2.0, 3.0], [3.0, 3.0], [3.0, 0.0]]
+            .iter()
+            .map(|&[y, z]| [0.0, y, z])
+            .collect();
+        let (triangles, degeneracy) = triangulate(&comb);
+        assert_eq!(degeneracy, None);
+        assert_eq!(triangles.len(), 6);
+        assert!((area(&comb, &triangles) - 7.0).abs() < 1e-5);
+        for t in triangles.iter() {
+            assert!(polygon_normal(&t.map(|i| comb[i]))[0] < 0.0, "{triangles:?}");
+        }
+    }
+
+    #[test]
+    fn fan_fallback() {
+        // all on a line, triangles 3 corners long included
+        for line in [&[[0.0, 0.0, 0.0], [1.0, 1.0, 1.0], [2.0, 2.0, 2.0], [3.0, 3.0, 3.0]][..], &[[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [2.0, 0.0, 0.0]]] {
+            let (triangles, degeneracy) = triangulate(line);
+            assert_eq!(degeneracy, Some(Degeneracy::ZeroArea));
+            assert_eq!(triangles.len(), line.len() - 2);
+        }
+        // a pentagon crossing itself, it has an area but no ear
+        let crossed = [[0.0, 0.0, 0.0], [0.0, 1.0, 0.0], [3.0, 0.0, 0.0], [1.0, 3.0, 0.0], [2.0, 3.0, 0.0]];
+        let (triangles, degeneracy) = triangulate(&crossed);
+        assert_eq!(degeneracy, Some(Degeneracy::SelfIntersecting));
+        assert_eq!(triangles.len(), crossed.len() - 2);
+        assert_eq!(triangulate(&[[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]), (vec![[0, 1, 2]], None));
+    }
+}
@@ -0,0 +1,264 @@
+use std::{collections::HashMap, f32::consts::PI};
+use crate::{bounds::Aabb, parsing::{Model, Vertex}, tangents::generate_tangents, vector::{cross, sub}};
+
+/// How texture coordinates are made for models without any
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Projection {
+    /// on the plane of the two largest sides of the box
+    #[default]
+    Planar,
+    /// every face on the side of the box it faces the most
+    Box,
+    /// longitude and latitude around the center of the box
+    Spherical,
+    /// angle around the vertical axis and height
+    Cylindrical,
+}
+
+impl Projection {
+    pub const ALL: [Projection; 4] = [Projection::Planar, Projection::Box, Projection::Spherical, Projection::Cylindrical];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Projection::Planar => "planar",
+            Projection::Box => "box",
+            Projection::Spherical => "spherical",
+            Projection::Cylindrical => "cylindrical",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Projection::ALL.into_iter().find(|projection| projection.name() == name)
+    }
+
+    /// The one after, back to the first after the last
+    pub fn next(&self) -> Self {
+        let i = Projection::ALL.iter().position(|projection| projection == self).unwrap_or(0);
+        Projection::ALL[(i + 1) % Projection::ALL.len()]
+    }
+}
+
+fn position(vertex: &Vertex) -> [f32; 3] {
+    [vertex.position.0, vertex.position.1, vertex.position.2]
+}
+
+fn planar(bounds: &Aabb, p: [f32; 3]) -> [f32; 2] {
+    let size = bounds.size();
+    let flat = (0..3).fold(2, |flat, i| if size[i] < size[flat] { i } else { flat });
+    let t = bounds.normalize(p);
+    match flat {
+        0 => [t[2], t[1]],
+        1 => [t[0], t[2]],
+        _ => [t[0], t[1]],
+    }
+}
+
+// the side of the box the face looks at, the texture isn't mirrored on the back sides
+fn boxed(bounds: &Aabb, normal: [f32; 3], p: [f32; 3]) -> [f32; 2] {
+    let axis = (0..3).fold(0, |axis, i| if normal[i].abs() > normal[axis].abs() { i } else { axis });
+    let t = bounds.normalize(p);
+    let [u, v] = match axis {
+        0 => [t[2], t[1]],
+        1 => [t[0], t[2]],
+        _ => [t[0], t[1]],
+    };
+    match (axis == 0) ^ (normal[axis] < 0.0) {
+        true => [1.0 - u, v],
+        false => [u, v],
+    }
+}
+
+// None on the vertical axis, where every angle is right
+fn longitude(bounds: &Aabb, p: [f32; 3]) -> Option<f32> {
+    let d = sub(p, bounds.center());
+    let epsilon = bounds.size().iter().fold(0.0f32, |a, &b| a.max(b)) * 1e-6;
+    (d[0].abs() > epsilon || d[2].abs() > epsilon).then(|| d[2].atan2(d[0]) / (2.0 * PI) + 0.5)
+}
+
+fn latitude(bounds: &Aabb, p: [f32; 3]) -> f32 {
+    let d = sub(p, bounds.center());
+    let r = crate::vector::length(d);
+    if r > 0.0 { (d[1] / r).asin() / PI + 0.5 } else { 0.5 }
+}
+
+// texture coordinates of the corners of a face
+fn triangle(projection: Projection, bounds: &Aabb, p: [[f32; 3]; 3]) -> [[f32; 2]; 3] {
+    let v = match projection {
+        Projection::Planar => return p.map(|p| planar(bounds, p)),
+        Projection::Box => {
+            let normal = cross(sub(p[1], p[0]), sub(p[2], p[0]));
+            return p.map(|p| boxed(bounds, normal, p));
+        },
+        Projection::Spherical => p.map(|p| latitude(bounds, p)),
+        Projection::Cylindrical => p.map(|p| bounds.normalize(p)[1]),
+    };
+    let mut u = p.map(|p| longitude(bounds, p));
+    // a face across the seam wraps around, its corners before it go past 1
+    let (lo, hi) = u.iter().flatten().fold((f32::MAX, f32::MIN), |(lo, hi), &u| (lo.min(u), hi.max(u)));
+    if hi - lo > 0.5 {
+        u.iter_mut().flatten().filter(|u| **u < 0.5).for_each(|u| *u += 1.0);
+    }
+    // corners on the axis take the angle of the others
+    let count = u.iter().flatten().count();
+    let mean = if count > 0 { u.iter().flatten().sum::<f32>() / count as f32 } else { 0.5 };
+    [0, 1, 2].map(|i| [u[i].unwrap_or(mean), v[i]])
+}
+
+/// Make the texture coordinates of the model again with `projection`.
+/// A vertex shared by faces that want other coordinates, along a seam or on
+/// another side of the box, is split: the copies are appended to the vertices
+/// and `model.seams` keeps where they come from, to undo it on the next projection.
+/// The tangents follow the new coordinates, splitting more vertices where needed.
+pub fn project(model: &mut Model, projection: Projection) {
+    let count = model.vertices.len() - model.seams.len();
+    for i in model.indices.iter_mut().filter(|i| **i as usize >= count) {
+        *i = model.seams[*i as usize - count];
+    }
+    model.vertices.truncate(count);
+    model.normals.truncate(count);
+    for scalars in model.scalars.iter_mut() {
+        scalars.values.truncate(count);
+    }
+    model.seams.clear();
+
+    let bounds = model.bounds;
+    let mut assigned: Vec<Option<[f32; 2]>> = vec![None; count];
+    let mut copies: HashMap<(u32, [u32; 2]), u32> = HashMap::new();
+    for face in model.indices.chunks_mut(3) {
+        let p = [0, 1, 2].map(|k| position(&model.vertices[face[k] as usize]));
+        for (i, uv) in face.iter_mut().zip(triangle(projection, &bounds, p)) {
+            let source = *i as usize;
+            match assigned[source] {
+                None => {
+                    assigned[source] = Some(uv);
+                    model.vertices[source].tex_coords = uv;
+                },
+                Some(first) if first == uv => {},
+                Some(_) => {
+                    *i = *copies.entry((*i, uv.map(f32::to_bits))).or_insert_with(|| {
+                        model.vertices.push(Vertex { tex_coords: uv, ..model.vertices[source] });
+                        model.normals.push(model.normals[source]);
+                        for scalars in model.scalars.iter_mut() {
+                            scalars.values.push(scalars.values[source]);
+                        }
+                        model.seams.push(source as u32);
+                        model.vertices.len() as u32 - 1
+                    });
+                },
+            }
+        }
+    }
+    // vertices of lines and points only
+    for (vertex, _) in model.vertices.iter_mut().zip(assigned).filter(|(_, uv)| uv.is_none()) {
+        let p = position(vertex);
+        vertex.tex_coords = match projection {
+            Projection::Planar | Projection::Box => planar(&bounds, p),
+            Projection::Spherical => [longitude(&bounds, p).unwrap_or(0.5), latitude(&bounds, p)],
+            Projection::Cylindrical => [longitude(&bounds, p).unwrap_or(0.5), bounds.normalize(p)[1]],
+        };
+    }
+    generate_tangents(model);
+    model.uv = Some(projection);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{boxed, project, Projection};
+    use crate::{parsing::{parsing, Model, ParseOptions}, vector::{cross, sub}};
+
+    fn model(obj: &str) -> Model {
+        parsing(obj.as_bytes(), &ParseOptions::default()).unwrap()
+    }
+
+    fn uv(model: &Model, i: u32) -> [f32; 2] {
+        model.vertices[i as usize].tex_coords
+    }
+
+    // the sides of a unit cube, two triangles each
+    fn cube() -> Model {
+        let mut obj = String::new();
+        for i in 0..8 {
+            obj.push_str(&format!("v {} {} {}\n", i & 1, i >> 1 & 1, i >> 2 & 1));
+        }
+        obj.push_str("f 1 3 4 2\nf 5 6 8 7\nf 1 2 6 5\nf 3 7 8 4\nf 1 5 7 3\nf 2 4 8 6\n");
+        model(&obj)
+    }
+
+    // the side of a cylinder around y, none of its vertices on the seam at -x
+    fn cylinder(segments: usize) -> Model {
+        let mut obj = String::new();
+        for i in 0..segments {
+            let angle = (i as f32 + 0.5) * std::f32::consts::TAU / segments as f32;
+            obj.push_str(&format!("v {} 0 {}\nv {0} 1 {1}\n", angle.cos(), angle.sin()));
+        }
+        for i in 0..segments {
+            let (b0, t0, b1, t1) = (2 * i + 1, 2 * i + 2, 2 * ((i + 1) % segments) + 1, 2 * ((i + 1) % segments) + 2);
+            obj.push_str(&format!("f {b0} {b1} {t1} {t0}\n"));
+        }
+        model(&obj)
+    }
+
+    // projecting again starts from the vertices of the file
+    fn assert_undone(model: &mut Model, vertices: usize, indices: &[u32]) {
+        project(model, Projection::Planar);
+        assert!(model.seams.is_empty());
+        assert_eq!(model.vertices.len(), vertices);
+        assert_eq!(model.indices, indices);
+    }
+
+    #[test]
+    fn planar() {
+        let model = model("v 0 0 0\nv 2 0 0\nv 2 1 0\nv 0 1 0\nf 1 2 3 4\n");
+        assert_eq!(model.uv, Some(Projection::Planar));
+        assert!(model.seams.is_empty());
+        let uvs: Vec<[f32; 2]> = model.vertices.iter().map(|vertex| vertex.tex_coords).collect();
+        assert_eq!(uvs, [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]]);
+    }
+
+    #[test]
+    fn box_sides() {
+        let mut model = cube();
+        let indices = model.indices.clone();
+        project(&mut model, Projection::Box);
+        // every side has corners of its own, unless two sides agree on them
+        assert_eq!(model.vertices.len(), 8 + model.seams.len());
+        assert!(model.seams.len() >= 8, "{}", model.seams.len());
+        for face in model.indices.chunks_exact(3) {
+            let p = [face[0], face[1], face[2]].map(|i| {
+                let (x, y, z) = model.vertices[i as usize].position;
+                [x, y, z]
+            });
+            let normal = cross(sub(p[1], p[0]), sub(p[2], p[0]));
+            for (k, &i) in face.iter().enumerate() {
+                assert_eq!(uv(&model, i), boxed(&model.bounds, normal, p[k]));
+            }
+        }
+        assert_undone(&mut model, 8, &indices);
+    }
+
+    #[test]
+    fn wrapping_seam() {
+        for projection in [Projection::Spherical, Projection::Cylindrical] {
+            let mut model = cylinder(8);
+            let indices = model.indices.clone();
+            project(&mut model, projection);
+            // the two vertices next to the seam have a copy 1 apart for the face across it
+            assert_eq!(model.seams.len(), 2, "{projection:?}");
+            for face in model.indices.chunks_exact(3) {
+                let u = [face[0], face[1], face[2]].map(|i| uv(&model, i)[0]);
+                let (lo, hi) = (u.iter().fold(f32::MAX, |a, &b| a.min(b)), u.iter().fold(f32::MIN, |a, &b| a.max(b)));
+                assert!(hi - lo < 0.5, "{projection:?}: {u:?}");
+            }
+            for (copy, &source) in model.seams.iter().enumerate() {
+                let copy = &model.vertices[model.vertices.len() - model.seams.len() + copy];
+                let source = &model.vertices[source as usize];
+                assert_eq!(copy.position, source.position);
+                assert_eq!((copy.tex_coords[0] - source.tex_coords[0]).abs(), 1.0);
+            }
+            // the normals and tangents follow the copies
+            assert_eq!(model.normals.len(), model.vertices.len());
+            assert_eq!(model.tangents.len(), model.vertices.len());
+            assert_undone(&mut model, 16, &indices);
+        }
+    }
+}
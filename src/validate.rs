@@ -0,0 +1,292 @@
+use std::{collections::HashMap, fmt};
+use crate::{parsing::{Model, Vertex}, vector::{cross, length, sub}};
+
+/// Problems found in a model. Topology is checked on positions: vertices split for
+/// their normals or texture coordinates are the same point of the surface.
+/// Triangles are indices of `Model::indices` chunks, edges and points are vertex indices.
+/// Indices past the vertices never get here, the importers reject them with their location.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub zero_area: Vec<usize>,
+    /// every copy of a face after the first, whatever their winding
+    pub duplicate_faces: Vec<usize>,
+    pub unreferenced: usize,
+    /// shared by more than two triangles
+    pub non_manifold_edges: Vec<[u32; 2]>,
+    /// where separate fans of triangles meet
+    pub non_manifold_vertices: Vec<u32>,
+    /// used by a single triangle
+    pub boundary_edges: Vec<[u32; 2]>,
+    pub boundary_loops: usize,
+    /// shared by two triangles going the same way along it
+    pub inconsistent_edges: Vec<[u32; 2]>,
+}
+
+impl Report {
+    pub fn is_clean(&self) -> bool {
+        self.zero_area.is_empty()
+            && self.duplicate_faces.is_empty()
+            && self.unreferenced == 0
+            && self.non_manifold_edges.is_empty()
+            && self.non_manifold_vertices.is_empty()
+            && self.boundary_edges.is_empty()
+            && self.inconsistent_edges.is_empty()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "zero area triangles: {}", self.zero_area.len())?;
+        writeln!(f, "duplicate faces: {}", self.duplicate_faces.len())?;
+        writeln!(f, "unreferenced vertices: {}", self.unreferenced)?;
+        writeln!(f, "non-manifold edges: {}", self.non_manifold_edges.len())?;
+        writeln!(f, "non-manifold vertices: {}", self.non_manifold_vertices.len())?;
+        writeln!(f, "boundary edges: {}", self.boundary_edges.len())?;
+        writeln!(f, "boundary loops: {}", self.boundary_loops)?;
+        write!(f, "inconsistent winding: {} edges", self.inconsistent_edges.len())
+    }
+}
+
+// union-find with path halving
+fn find(parents: &mut [usize], mut i: usize) -> usize {
+    while parents[i] != i {
+        parents[i] = parents[parents[i]];
+        i = parents[i];
+    }
+    i
+}
+
+fn union(parents: &mut [usize], a: usize, b: usize) {
+    let (a, b) = (find(parents, a), find(parents, b));
+    parents[a] = b;
+}
+
+fn position(vertex: &Vertex) -> [f32; 3] {
+    [vertex.position.0, vertex.position.1, vertex.position.2]
+}
+
+pub fn validate(model: &Model) -> Report {
+    let mut report = Report { unreferenced: model.unreferenced.len(), ..Report::default() };
+
+    // one id per position, with the first vertex found there. -0.0 and 0.0 are the same coordinate
+    let mut ids: HashMap<[u32; 3], u32> = HashMap::new();
+    let mut vertex_of: Vec<u32> = Vec::new();
+    let id: Vec<u32> = model.vertices.iter()
+        .enumerate()
+        .map(|(i, vertex)| *ids.entry(position(vertex).map(|x| (x + 0.0).to_bits())).or_insert_with(|| {
+            vertex_of.push(i as u32);
+            vertex_of.len() as u32 - 1
+        }))
+        .collect();
+    let edge = |a: u32, b: u32| [vertex_of[a as usize], vertex_of[b as usize]];
+
+    let size = model.bounds.size();
+    let extent = size[0].max(size[1]).max(size[2]);
+    let mut faces: HashMap<[u32; 3], usize> = HashMap::new();
+    let mut triangles: Vec<[u32; 3]> = Vec::new();
+    for (t, triangle) in model.indices.chunks_exact(3).enumerate() {
+        let [a, b, c] = [0, 1, 2].map(|k| position(&model.vertices[triangle[k] as usize]));
+        if length(cross(sub(b, a), sub(c, a))) <= f32::EPSILON * extent * extent {
+            report.zero_area.push(t);
+        }
+        let corners = [0, 1, 2].map(|k| id[triangle[k] as usize]);
+        let mut key = corners;
+        key.sort();
+        if *faces.entry(key).and_modify(|copies| *copies += 1).or_insert(1) > 1 {
+            report.duplicate_faces.push(t);
+        }
+        // collapsed triangles have no edges to speak of
+        if key[0] != key[1] && key[1] != key[2] {
+            triangles.push(corners);
+        }
+    }
+
+    // triangles using each edge, and how many go from its lower id to its higher one
+    let mut edges: HashMap<[u32; 2], (usize, usize)> = HashMap::new();
+    for corners in triangles.iter() {
+        for k in 0..3 {
+            let (a, b) = (corners[k], corners[(k + 1) % 3]);
+            let uses = edges.entry([a.min(b), a.max(b)]).or_insert((0, 0));
+            uses.0 += 1;
+            uses.1 += (a < b) as usize;
+        }
+    }
+    let mut boundary_parents: Vec<usize> = (0..vertex_of.len()).collect();
+    let mut on_boundary = vec![false; vertex_of.len()];
+    for (&[a, b], &(uses, forward)) in edges.iter() {
+        match uses {
+            1 => {
+                report.boundary_edges.push(edge(a, b));
+                union(&mut boundary_parents, a as usize, b as usize);
+                on_boundary[a as usize] = true;
+                on_boundary[b as usize] = true;
+            },
+            2 if forward != 1 => report.inconsistent_edges.push(edge(a, b)),
+            2 => {},
+            _ => report.non_manifold_edges.push(edge(a, b)),
+        }
+    }
+    report.boundary_loops = (0..vertex_of.len())
+        .filter(|&v| on_boundary[v] && find(&mut boundary_parents, v) == v)
+        .count();
+
+    // the corners around a vertex are joined through the edges they share,
+    // more than one group of them is a bowtie
+    let mut corner_parents: Vec<usize> = (0..triangles.len() * 3).collect();
+    let mut first: HashMap<[u32; 2], usize> = HashMap::new();
+    for (t, corners) in triangles.iter().enumerate() {
+        for k in 0..3 {
+            let slot = t * 3 + k;
+            for other in [corners[(k + 1) % 3], corners[(k + 2) % 3]] {
+                match first.get(&[corners[k], other]) {
+                    Some(&shared) => union(&mut corner_parents, slot, shared),
+                    None => {
+                        first.insert([corners[k], other], slot);
+                    },
+                }
+            }
+        }
+    }
+    let mut fans: HashMap<u32, usize> = HashMap::new();
+    for (t, corners) in triangles.iter().enumerate() {
+        for (k, &corner) in corners.iter().enumerate() {
+            let slot = t * 3 + k;
+            if find(&mut corner_parents, slot) == slot {
+                *fans.entry(corner).or_insert(0) += 1;
+            }
+        }
+    }
+    report.non_manifold_vertices = fans.into_iter().filter(|&(_, fans)| fans > 1).map(|(v, _)| vertex_of[v as usize]).collect();
+    report.non_manifold_vertices.sort();
+    for edges in [&mut report.non_manifold_edges, &mut report.boundary_edges, &mut report.inconsistent_edges] {
+        edges.sort();
+    }
+    report
+}
+
+/// What the viewer draws on top of the model to show the problems of `report`
+pub struct Overlay {
+    pub vertices: Vec<Vertex>,
+    pub triangles: Vec<u32>,
+    pub lines: Vec<u32>,
+    pub points: Vec<u32>,
+}
+
+const RED: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+const ORANGE: [f32; 4] = [1.0, 0.5, 0.0, 1.0];
+const YELLOW: [f32; 4] = [1.0, 1.0, 0.0, 1.0];
+const MAGENTA: [f32; 4] = [1.0, 0.0, 1.0, 1.0];
+const CYAN: [f32; 4] = [0.0, 1.0, 1.0, 1.0];
+
+/// Duplicate faces in red, zero area triangles as red edges and points since they
+/// have no surface to fill, non-manifold edges and vertices in magenta, boundaries
+/// in yellow, inconsistent winding in orange and unreferenced vertices in cyan
+pub fn overlay(model: &Model, report: &Report) -> Overlay {
+    let mut overlay = Overlay { vertices: Vec::new(), triangles: Vec::new(), lines: Vec::new(), points: Vec::new() };
+    let mut add = |position: (f32, f32, f32), color: [f32; 4]| -> u32 {
+        overlay.vertices.push(Vertex { position, tex_coords: [0.0, 0.0], color });
+        overlay.vertices.len() as u32 - 1
+    };
+    let at = |i: u32| model.vertices[i as usize].position;
+    let (mut triangles, mut lines, mut points) = (Vec::new(), Vec::new(), Vec::new());
+    for &t in report.duplicate_faces.iter() {
+        triangles.extend(model.indices[t * 3..t * 3 + 3].iter().map(|&i| add(at(i), RED)));
+    }
+    for &t in report.zero_area.iter() {
+        let corners = &model.indices[t * 3..t * 3 + 3];
+        lines.extend((0..3).flat_map(|k| [corners[k], corners[(k + 1) % 3]]).map(|i| add(at(i), RED)));
+        // triangles collapsed to a point have edges of no length
+        points.extend(corners.iter().map(|&i| add(at(i), RED)));
+    }
+    let edges = [(&report.non_manifold_edges, MAGENTA), (&report.boundary_edges, YELLOW), (&report.inconsistent_edges, ORANGE)];
+    for (edges, color) in edges {
+        lines.extend(edges.iter().flatten().map(|&i| add(at(i), color)));
+    }
+    points.extend(report.non_manifold_vertices.iter().map(|&i| add(at(i), MAGENTA)));
+    points.extend(model.unreferenced.iter().map(|&[x, y, z]| add((x, y, z), CYAN)));
+    overlay.triangles = triangles;
+    overlay.lines = lines;
+    overlay.points = points;
+    overlay
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{overlay, validate, Report};
+    use crate::parsing::{parsing, Model, ParseOptions};
+
+    fn model(obj: &str) -> Model {
+        parsing(obj.as_bytes(), &ParseOptions::default()).unwrap()
+    }
+
+    fn report(obj: &str) -> Report {
+        validate(&model(obj))
+    }
+
+    const TETRAHEDRON: &str = "v 0 0 0\nv 1 0 0\nv 0 1 0\nv 0 0 1\nf 1 3 2\nf 1 2 4\nf 2 3 4\nf 3 1 4\n";
+
+    #[test]
+    fn closed() {
+        let report = report(TETRAHEDRON);
+        assert!(report.is_clean(), "{report}");
+    }
+
+    #[test]
+    fn signed_zeros() {
+        // the first corner again as -0, used by the last face
+        let obj = TETRAHEDRON.replace("f 3 1 4", "v -0 0 -0\nf 3 5 4");
+        let report = report(&obj);
+        assert!(report.is_clean(), "{report}");
+    }
+
+    #[test]
+    fn boundary() {
+        // a square, then two squares apart
+        let report = report("v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3\nf 1 3 4\n");
+        assert_eq!(report.boundary_edges.len(), 4);
+        assert_eq!(report.boundary_loops, 1);
+        assert!(report.inconsistent_edges.is_empty() && report.non_manifold_edges.is_empty());
+        let report = self::report("v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nv 5 0 0\nv 6 0 0\nv 6 1 0\nv 5 1 0\nf 1 2 3 4\nf 5 6 7 8\n");
+        assert_eq!(report.boundary_edges.len(), 8);
+        assert_eq!(report.boundary_loops, 2);
+        // a tetrahedron with a face missing
+        let open = TETRAHEDRON.trim_end().rsplit_once('\n').unwrap().0;
+        assert_eq!(self::report(open).boundary_loops, 1);
+    }
+
+    #[test]
+    fn bowtie() {
+        // two triangles touching at a corner
+        let report = report("v 0 0 0\nv 1 0 0\nv 1 1 0\nv -1 0 0\nv -1 -1 0\nf 1 2 3\nf 1 4 5\n");
+        assert_eq!(report.non_manifold_vertices.len(), 1);
+        assert_eq!(report.boundary_loops, 1);
+        // three triangles on an edge
+        let report = self::report("v 0 0 0\nv 1 0 0\nv 0 1 0\nv 0 -1 0\nv 0 0 1\nf 1 2 3\nf 2 1 4\nf 1 2 5\n");
+        assert_eq!(report.non_manifold_edges, [[0, 1]]);
+    }
+
+    #[test]
+    fn flipped_winding() {
+        let flipped = TETRAHEDRON.replace("f 2 3 4", "f 2 4 3");
+        let report = report(&flipped);
+        // the three edges of the flipped face
+        assert_eq!(report.inconsistent_edges.len(), 3);
+        assert!(report.boundary_edges.is_empty());
+    }
+
+    #[test]
+    fn faces() {
+        // an unused vertex, a copy of a face wound the other way and a collapsed triangle
+        let obj = format!("{TETRAHEDRON}v 9 9 9\nv 2 0 0\nf 1 2 3\nf 1 2 6\n");
+        let model = model(&obj);
+        let report = validate(&model);
+        assert_eq!(report.unreferenced, 1);
+        assert_eq!(report.duplicate_faces, [4]);
+        assert_eq!(report.zero_area, [5]);
+        // the collapsed triangle has no surface to fill, it's drawn as its edges and corners
+        let overlay = overlay(&model, &report);
+        assert_eq!(overlay.triangles.len(), 3);
+        assert_eq!(overlay.lines.len() - 2 * (report.non_manifold_edges.len() + report.boundary_edges.len() + report.inconsistent_edges.len()), 6);
+        assert_eq!(overlay.points.len(), 3 + 1);
+    }
+}
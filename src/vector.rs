@@ -0,0 +1,29 @@
+pub fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+pub fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+pub fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+pub fn length(a: [f32; 3]) -> f32 {
+    dot(a, a).sqrt()
+}
+
+/// Returns the zero vector unchanged instead of dividing by zero
+pub fn normalize(a: [f32; 3]) -> [f32; 3] {
+    let len = length(a);
+    if len > 0.0 {
+        [a[0] / len, a[1] / len, a[2] / len]
+    } else {
+        a
+    }
+}
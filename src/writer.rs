@@ -0,0 +1,266 @@
+use std::{fs::File, io::{self, BufWriter, Write}, path::{Component, Path, PathBuf}};
+use crate::{material::Material, parsing::Model};
+
+// floats are written with `precision` decimals, or with as many as needed to read them back exactly
+struct Floats(Option<usize>);
+
+impl Floats {
+    fn write<W: Write>(&self, out: &mut W, values: &[f32]) -> io::Result<()> {
+        for value in values {
+            match self.0 {
+                Some(precision) => write!(out, " {value:.precision$}")?,
+                None => write!(out, " {value}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// obj numbers of the vertices, from 1. Copies made for the tangents alone are the
+// same as their vertex, they are written once and the parser splits them again.
+// Returns the vertices written too.
+fn numbers(model: &Model) -> (Vec<u32>, Vec<usize>) {
+    let count = model.vertices.len() - model.seams.len();
+    let (mut numbers, mut written) = (Vec::with_capacity(model.vertices.len()), Vec::new());
+    for (i, vertex) in model.vertices.iter().enumerate() {
+        let source = (i >= count).then(|| model.seams[i - count] as usize);
+        let same = source.filter(|&source| {
+            let other = &model.vertices[source];
+            (vertex.position, vertex.tex_coords, vertex.color) == (other.position, other.tex_coords, other.color)
+                && model.normals[i].normal == model.normals[source].normal
+        });
+        match same {
+            Some(source) => numbers.push(numbers[source]),
+            None => {
+                written.push(i);
+                numbers.push(written.len() as u32);
+            },
+        }
+    }
+    (numbers, written)
+}
+
+/// Write the model as obj statements: one `v`/`vt`/`vn` per vertex, then the faces,
+/// lines and points with the object, group and material of their submesh.
+/// `mtllib` names the material library, if there's one.
+pub fn write_obj<W: Write>(model: &Model, out: &mut W, precision: Option<usize>, mtllib: Option<&str>) -> io::Result<()> {
+    let floats = Floats(precision);
+    if let Some(mtllib) = mtllib {
+        writeln!(out, "mtllib {mtllib}")?;
+    }
+    let (numbers, written) = numbers(model);
+    for vertex in written.iter().map(|&i| &model.vertices[i]) {
+        let (x, y, z) = vertex.position;
+        write!(out, "v")?;
+        floats.write(out, &[x, y, z])?;
+        if model.has_colors {
+            floats.write(out, &vertex.color)?;
+        }
+        writeln!(out)?;
+    }
+    for vertex in written.iter().map(|&i| &model.vertices[i]) {
+        write!(out, "vt")?;
+        floats.write(out, &vertex.tex_coords)?;
+        writeln!(out)?;
+    }
+    for normal in written.iter().map(|&i| &model.normals[i]) {
+        let (x, y, z) = normal.normal;
+        write!(out, "vn")?;
+        floats.write(out, &[x, y, z])?;
+        writeln!(out)?;
+    }
+
+    let (mut object, mut group, mut material) = (None, None, None);
+    for submesh in model.submeshes.iter() {
+        // `o` starts over in the default group
+        if submesh.object != object {
+            object = submesh.object.clone();
+            group = None;
+            writeln!(out, "o {}", object.as_deref().unwrap_or(""))?;
+        }
+        if submesh.group != group {
+            group = submesh.group.clone();
+            writeln!(out, "g {}", group.as_deref().unwrap_or(""))?;
+        }
+        // a bare `usemtl` goes back to no material
+        if submesh.material != material {
+            material = submesh.material.clone();
+            match material.as_deref() {
+                Some(name) => writeln!(out, "usemtl {name}")?,
+                None => writeln!(out, "usemtl")?,
+            }
+        }
+        for triangle in model.indices[submesh.start..submesh.end].chunks(3) {
+            write!(out, "f")?;
+            for i in triangle.iter().map(|&i| numbers[i as usize]) {
+                write!(out, " {i}/{i}/{i}")?;
+            }
+            writeln!(out)?;
+        }
+        for segment in model.lines[submesh.line_start..submesh.line_end].chunks(2) {
+            let (a, b) = (numbers[segment[0] as usize], numbers[segment[1] as usize]);
+            writeln!(out, "l {a}/{a} {b}/{b}")?;
+        }
+        for point in model.points[submesh.point_start..submesh.point_end].iter().map(|&i| numbers[i as usize]) {
+            writeln!(out, "p {point}/{point}")?;
+        }
+    }
+    Ok(())
+}
+
+// absolute, without `.` and `..`
+fn lexical(path: &Path) -> Option<PathBuf> {
+    let path = std::path::absolute(if path.as_os_str().is_empty() { Path::new(".") } else { path }).ok()?;
+    let mut normal = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {},
+            Component::ParentDir => {
+                normal.pop();
+            },
+            component => normal.push(component),
+        }
+    }
+    Some(normal)
+}
+
+// `path` as seen from `dir`, absolute when there's no way from one to the other
+fn relative(path: &Path, dir: &Path) -> PathBuf {
+    let (Some(path), Some(dir)) = (lexical(path), lexical(dir)) else {
+        return path.to_path_buf();
+    };
+    // not even the root or the drive in common
+    let common = path.components().zip(dir.components()).take_while(|(a, b)| a == b).count();
+    if common == 0 {
+        return path;
+    }
+    dir.components()
+        .skip(common)
+        .map(|_| Component::ParentDir)
+        .chain(path.components().skip(common))
+        .collect()
+}
+
+/// Write the materials as a material library saved in `dir`, texture paths relative to it
+pub fn write_mtl<W: Write>(materials: &[Material], out: &mut W, precision: Option<usize>, dir: &Path) -> io::Result<()> {
+    let floats = Floats(precision);
+    for material in materials.iter() {
+        writeln!(out, "newmtl {}", material.name)?;
+        for (keyword, rgb) in [("Ka", material.ambient), ("Kd", material.diffuse), ("Ks", material.specular)] {
+            write!(out, "{keyword}")?;
+            floats.write(out, &rgb)?;
+            writeln!(out)?;
+        }
+        write!(out, "Ns")?;
+        floats.write(out, &[material.shininess])?;
+        write!(out, "\nd")?;
+        floats.write(out, &[material.opacity])?;
+        writeln!(out, "\nillum {}", material.illum)?;
+        let maps = [
+            ("map_Kd", &material.diffuse_map),
+            ("map_Ks", &material.specular_map),
+            ("map_Bump", &material.bump_map),
+            ("map_d", &material.opacity_map),
+        ];
+        for (keyword, map) in maps {
+            if let Some(map) = map {
+                writeln!(out, "{keyword} {}", relative(map, dir).display())?;
+            }
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// Save the model as an obj file, with a material library named after it when it has materials
+pub fn save(model: &Model, path: &Path, precision: Option<usize>) -> io::Result<()> {
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let mtllib = match model.materials.is_empty() {
+        true => None,
+        false => {
+            let mtl_path = path.with_extension("mtl");
+            let mut out = BufWriter::new(File::create(&mtl_path)?);
+            write_mtl(&model.materials, &mut out, precision, dir)?;
+            out.flush()?;
+            mtl_path.file_name().map(|name| name.to_string_lossy().into_owned())
+        },
+    };
+    let mut out = BufWriter::new(File::create(path)?);
+    write_obj(model, &mut out, precision, mtllib.as_deref())?;
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::BufReader};
+    use std::path::Path;
+    use crate::{loader::load, material::{parse_mtl, Material}, parsing::{parsing, ParseOptions}};
+    use super::{save, write_mtl, write_obj};
+
+    // materials set, taken away then set again
+    const MATERIALS: &str = "v 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl red\nf 1 2 3\nusemtl\nf 1 2 3\n\
+        g side\nusemtl blue\nf 1 2 3\nusemtl\nl 1 2\np 3\n";
+
+    // parse, write then parse again every obj of resources/, both models must be the same
+    #[test]
+    fn round_trip() {
+        let options = ParseOptions::default();
+        let mut objs = vec![(String::from("materials"), MATERIALS.as_bytes().to_vec())];
+        for entry in fs::read_dir("resources").unwrap() {
+            let path = entry.unwrap().path();
+            // ._name.obj files are macOS metadata
+            let hidden = path.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.'));
+            if !hidden && path.extension().is_some_and(|extension| extension == "obj") {
+                objs.push((path.display().to_string(), fs::read(&path).unwrap()));
+            }
+        }
+        for (name, obj) in objs {
+            let model = parsing(BufReader::new(&obj[..]), &options).unwrap();
+            let mut obj = Vec::new();
+            write_obj(&model, &mut obj, None, None).unwrap();
+            let again = parsing(&obj[..], &options).unwrap();
+
+            // vertices and normals have no PartialEq, they are compared through their Debug output
+            assert_eq!(format!("{:?}", model.vertices), format!("{:?}", again.vertices), "{name}: vertices");
+            assert_eq!(format!("{:?}", model.normals), format!("{:?}", again.normals), "{name}: normals");
+            assert_eq!(model.indices, again.indices, "{name}: indices");
+            assert_eq!(model.lines, again.lines, "{name}: lines");
+            assert_eq!(model.points, again.points, "{name}: points");
+            assert_eq!(model.submeshes, again.submeshes, "{name}: submeshes");
+            assert_eq!(model.has_colors, again.has_colors, "{name}: colors");
+        }
+    }
+
+    #[test]
+    fn mtl_round_trip() {
+        let mut material = Material::new("glass");
+        material.diffuse = [0.1, 0.2, 0.3];
+        material.opacity = 0.25;
+        material.illum = 4;
+        material.diffuse_map = Some(Path::new("textures/glass.png").to_path_buf());
+        let materials = vec![Material::new("default"), material];
+        let mut mtl = Vec::new();
+        write_mtl(&materials, &mut mtl, None, Path::new("")).unwrap();
+        let again = parse_mtl(std::str::from_utf8(&mtl).unwrap(), Path::new("")).unwrap();
+        assert_eq!(materials, again);
+    }
+
+    #[test]
+    fn save_elsewhere() {
+        let dir = std::env::temp_dir().join(format!("scop-save-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("in/textures")).unwrap();
+        fs::create_dir_all(dir.join("out")).unwrap();
+        fs::write(dir.join("in/model.mtl"), "newmtl wood\nmap_Kd textures/wood.png\n").unwrap();
+        fs::write(dir.join("in/model.obj"), "mtllib model.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl wood\nf 1 2 3\n").unwrap();
+        let model = load(&dir.join("in/model.obj"), &ParseOptions::default(), None).unwrap();
+        save(&model, &dir.join("out/copy.obj"), None).unwrap();
+        let mtl = fs::read_to_string(dir.join("out/copy.mtl")).unwrap();
+        assert!(mtl.contains("map_Kd ../in/textures/wood.png\n"), "{mtl}");
+        // the copy finds the same texture
+        let copy = load(&dir.join("out/copy.obj"), &ParseOptions::default(), None).unwrap();
+        let texture = copy.materials[0].diffuse_map.as_ref().unwrap();
+        assert_eq!(texture, &dir.join("out/../in/textures/wood.png"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}